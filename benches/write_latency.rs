@@ -0,0 +1,170 @@
+//! Measures how long a command takes from being queued until the device sees
+//! it, while the device floods the app with volume updates, and how much CPU
+//! an idle link burns. Runs the app side through the same reader and writer
+//! loops the serial session uses, over a Unix socket pair.
+//!
+//! `cargo bench --bench write_latency`
+
+#[cfg(unix)]
+fn main() {
+    bench::run();
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The benchmark needs a Unix socket pair");
+}
+
+#[cfg(unix)]
+mod bench {
+    use std::{
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+        sync::{
+            Arc, Mutex,
+            atomic::{AtomicBool, Ordering},
+            mpsc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use audiomixer_app2::protocol::{
+        CommandIn, CommandOut, CommandsOut, FrameDecoder, Link, READ_TIMEOUT, SetVolumeProps,
+        link_frame, read_loop, write_loop,
+    };
+
+    const COMMANDS: usize = 2_000;
+    /// Volume updates the simulated device sends per second while measuring.
+    const LOAD_RATE: u32 = 5_000;
+    const IDLE_PERIOD: Duration = Duration::from_secs(3);
+
+    pub fn run() {
+        let (host, device) = UnixStream::pair().unwrap();
+        host.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+        let host_writer = host.try_clone().unwrap();
+
+        let link = Arc::new(Mutex::new(Link::new()));
+        let closed = Arc::new(AtomicBool::new(false));
+        let (commands_tx, commands_rx) = futures_channel::mpsc::unbounded::<CommandsOut>();
+
+        let writer = {
+            let link = link.clone();
+            thread::spawn(move || {
+                smol::block_on(write_loop(host_writer, &link, commands_rx, |_| {}))
+            })
+        };
+        let reader = {
+            let link = link.clone();
+            let closed = closed.clone();
+            thread::spawn(move || read_loop(host, &link, &closed, |_| {}, |_| {}))
+        };
+
+        // Device side: acknowledge every frame and report when a SetVolume arrives
+        let device_writer = Arc::new(Mutex::new(device.try_clone().unwrap()));
+        let (arrived_tx, arrived_rx) = mpsc::channel::<Instant>();
+        {
+            let device_writer = device_writer.clone();
+            let mut device = device;
+            thread::spawn(move || {
+                let mut buffer = [0u8; 1024];
+                let mut decoder = FrameDecoder::new();
+                while let Ok(n) = device.read(&mut buffer) {
+                    if n == 0 {
+                        break;
+                    }
+                    for &byte in &buffer[..n] {
+                        let Some(Ok(data)) = decoder.push(byte) else {
+                            continue;
+                        };
+                        if data.len() < 4 {
+                            continue;
+                        }
+                        let ack = link_frame(0, &[CommandIn::Ack as u8, data[0]]);
+                        let _ = device_writer.lock().unwrap().write_all(&ack);
+                        if data[1] == CommandOut::SetVolume as u8 {
+                            let _ = arrived_tx.send(Instant::now());
+                        }
+                    }
+                }
+            });
+        }
+
+        let loaded = Arc::new(AtomicBool::new(true));
+        let load = {
+            let loaded = loaded.clone();
+            thread::spawn(move || {
+                let interval = Duration::from_secs(1) / LOAD_RATE;
+                let mut seq: u8 = 0;
+                while loaded.load(Ordering::Relaxed) {
+                    seq = seq.wrapping_add(1);
+                    let frame = link_frame(seq, &[CommandIn::SendVolume as u8, 1, seq % 101]);
+                    if device_writer.lock().unwrap().write_all(&frame).is_err() {
+                        break;
+                    }
+                    thread::sleep(interval);
+                }
+            })
+        };
+
+        let mut latencies = Vec::with_capacity(COMMANDS);
+        for volume in (0..100u8).cycle().take(COMMANDS) {
+            let queued = Instant::now();
+            commands_tx
+                .unbounded_send(CommandsOut::SetVolume(SetVolumeProps {
+                    channel: 1,
+                    volume,
+                    fine_position: None,
+                }))
+                .unwrap();
+            let arrived = arrived_rx.recv().unwrap();
+            latencies.push(arrived.duration_since(queued));
+        }
+        loaded.store(false, Ordering::Relaxed);
+        load.join().unwrap();
+
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        println!(
+            "write latency over {} commands with {} updates/s incoming: min {:?}, p50 {:?}, p99 {:?}, max {:?}",
+            COMMANDS,
+            LOAD_RATE,
+            latencies[0],
+            percentile(50),
+            percentile(99),
+            latencies[latencies.len() - 1]
+        );
+
+        // Let pending acknowledgements settle before measuring the idle link
+        thread::sleep(Duration::from_millis(500));
+        match (cpu_time(), cpu_time_after(IDLE_PERIOD)) {
+            (Some(before), Some(after)) => println!(
+                "idle CPU over {:?}: {:.2}%",
+                IDLE_PERIOD,
+                (after - before).as_secs_f64() / IDLE_PERIOD.as_secs_f64() * 100.0
+            ),
+            _ => println!("idle CPU: not available on this platform"),
+        }
+
+        drop(commands_tx);
+        closed.store(true, Ordering::Relaxed);
+        writer.join().unwrap().unwrap();
+        reader.join().unwrap().unwrap();
+    }
+
+    fn cpu_time_after(period: Duration) -> Option<Duration> {
+        thread::sleep(period);
+        cpu_time()
+    }
+
+    /// User plus system time of the whole process, read from procfs.
+    fn cpu_time() -> Option<Duration> {
+        let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+        // Skip past the command name, it may contain spaces
+        let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        // Clock ticks, USER_HZ is 100 on every Linux target we ship for
+        Some(Duration::from_millis((utime + stime) * 10))
+    }
+}
@@ -0,0 +1,19 @@
+//! Arbitrary bytes from the device must never panic the reader.
+//!
+//! `cargo +nightly fuzz run decode`
+
+#![no_main]
+
+use audiomixer_app2::protocol::{Codec, Inbound, Link};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // The whole receive path, as `read_loop` runs it
+    let mut link = Link::new();
+    Inbound::new().feed(&mut link, data, |_| {});
+
+    // Payloads straight into the codec, skipping framing and checksums
+    let codec = Codec::new();
+    let _ = codec.decode(data);
+    let _ = codec.decode_out(data);
+});
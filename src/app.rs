@@ -1,6 +1,6 @@
 use crate::{
     Data, DataChannel,
-    pages::{Loading, Main},
+    pages::{Devices, Loading, Main, Ports, Settings, Update},
 };
 
 use freya::{
@@ -28,4 +28,12 @@ pub enum Route {
     Loading,
     #[route("/main")]
     Main,
+    #[route("/ports")]
+    Ports,
+    #[route("/settings")]
+    Settings,
+    #[route("/update")]
+    Update,
+    #[route("/devices")]
+    Devices,
 }
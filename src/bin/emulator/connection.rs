@@ -0,0 +1,184 @@
+use std::{
+    io::{Read, Write},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+};
+
+use audiomixer_app2::protocol::FrameDecoder;
+use serialport::{SerialPort, TTYPort};
+
+use crate::device::Device;
+
+/// A pseudo-terminal the app can open, plus the thread answering it.
+pub struct Connection {
+    writer: Arc<Mutex<TTYPort>>,
+    stop: Arc<AtomicBool>,
+    reader: JoinHandle<()>,
+    // Kept open so reading the master doesn't fail while the app is away
+    _slave: TTYPort,
+}
+
+impl Connection {
+    pub fn open(link: &Path, device: Arc<Mutex<Device>>) -> serialport::Result<Self> {
+        let (mut master, slave) = TTYPort::pair()?;
+        let slave_name = slave.name().unwrap_or_default();
+
+        // Point the stable path at the new pty, the app keeps using the same port
+        let _ = std::fs::remove_file(link);
+        std::os::unix::fs::symlink(&slave_name, link)?;
+        println!("Emulating a mixer on {} -> {}", link.display(), slave_name);
+
+        let writer = Arc::new(Mutex::new(master.try_clone_native()?));
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let writer = writer.clone();
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut buffer = [0u8; 1024];
+                let mut decoder = FrameDecoder::new();
+                'read: while !stop.load(Ordering::Relaxed) {
+                    let n = match master.read(&mut buffer) {
+                        Ok(n) => n,
+                        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                        Err(e) => {
+                            eprintln!("Failed to read the pty: {}", e);
+                            break;
+                        }
+                    };
+
+                    for &byte in &buffer[..n] {
+                        match decoder.push(byte) {
+                            Some(Ok(data)) => {
+                                let (frames, rebooting) = {
+                                    let mut device = device.lock().unwrap();
+                                    (device.handle_frame(&data), device.rebooting())
+                                };
+                                for frame in frames {
+                                    write_frame(&writer, &frame);
+                                }
+                                if rebooting {
+                                    break 'read;
+                                }
+                            }
+                            Some(Err(e)) => eprintln!("Dropping bytes from the app: {}", e),
+                            None => {}
+                        }
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            writer,
+            stop,
+            reader,
+            _slave: slave,
+        })
+    }
+
+    pub fn write(&self, frame: &[u8]) {
+        write_frame(&self.writer, frame);
+    }
+
+    /// Closes the pty, to the app this looks like the device being unplugged.
+    pub fn close(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.reader.join();
+    }
+
+    /// Blocks until the app goes away or the device reboots.
+    pub fn wait(self) {
+        let _ = self.reader.join();
+    }
+}
+
+fn write_frame(writer: &Mutex<TTYPort>, frame: &[u8]) {
+    if let Err(e) = writer.lock().unwrap().write_all(frame) {
+        eprintln!("Failed to write to the pty: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use audiomixer_app2::protocol::{
+        Capabilities, Codec, CommandsIn, CommandsOut, DeviceInfo, Link, PROTOCOL_VERSION,
+    };
+
+    use super::*;
+
+    /// Frames the emulator wrote back, acknowledgements already consumed by
+    /// `link`.
+    fn read_commands(port: &mut TTYPort, link: &mut Link, count: usize) -> Vec<CommandsIn> {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut decoder = FrameDecoder::new();
+        let mut commands = Vec::new();
+        let mut buffer = [0u8; 256];
+        while commands.len() < count {
+            assert!(Instant::now() < deadline, "the emulator did not answer");
+            let n = match port.read(&mut buffer) {
+                Ok(n) => n,
+                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
+                Err(e) => panic!("failed to read the pty: {e}"),
+            };
+            for &byte in &buffer[..n] {
+                if let Some(data) = decoder.push(byte)
+                    && let Some(payload) = link.unwrap(&data.unwrap()).unwrap()
+                {
+                    commands.push(Codec::new().decode(&payload).unwrap());
+                }
+            }
+        }
+        commands
+    }
+
+    #[test]
+    fn answers_the_app_over_the_pty() {
+        let path = std::env::temp_dir().join(format!("audiomixer-emulator-{}", std::process::id()));
+        let info = DeviceInfo {
+            sliders: Vec::new(),
+            buttons: Vec::new(),
+            encoders: Vec::new(),
+        };
+        let device = Arc::new(Mutex::new(Device::new(
+            info.clone(),
+            None,
+            Capabilities::HEARTBEAT,
+        )));
+        let connection = Connection::open(&path, device).unwrap();
+
+        let mut port = serialport::new(path.to_string_lossy(), 115_200)
+            .timeout(Duration::from_millis(50))
+            .open_native()
+            .unwrap();
+        let mut link = Link::new();
+        let codec = Codec::new();
+        for command in [
+            CommandsOut::Hello,
+            CommandsOut::RequestInfo,
+            CommandsOut::Ping(7),
+        ] {
+            port.write_all(&link.wrap(&codec.encode(command))).unwrap();
+        }
+
+        let replies = read_commands(&mut port, &mut link, 3);
+        assert!(matches!(
+            &replies[0],
+            CommandsIn::Hello(hello) if hello.protocol_version == PROTOCOL_VERSION
+                && hello.capabilities == Capabilities::HEARTBEAT
+        ));
+        assert_eq!(replies[1], CommandsIn::SendInfo(info));
+        assert_eq!(replies[2], CommandsIn::Pong(7));
+        // Every frame the app sent was acknowledged
+        assert_eq!(link.stats.acks, 3);
+        assert!(link.next_retransmission().is_none());
+
+        connection.close();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
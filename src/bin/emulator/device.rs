@@ -0,0 +1,603 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use audiomixer_app2::protocol::{
+    ButtonEvent, ButtonEventKind, Capabilities, Codec, CommandIn, CommandsIn, CommandsOut,
+    DeviceInfo, EncoderEvent, FRAME_DELIMITER, FirmwareImage, FirmwareVersion, HelloInfo,
+    PROTOCOL_VERSION, UpdateChunkProps, VolumeInfo, crc16, crc32, encode_frame, link_frame,
+    percent_position, position_percent, split_link_frame,
+};
+
+/// Version reported in the Hello reply until an update replaces it.
+const FIRMWARE_VERSION: FirmwareVersion = FirmwareVersion {
+    major: 0,
+    minor: 1,
+    patch: 0,
+};
+/// A command id no firmware uses.
+const UNKNOWN_COMMAND: u8 = 0x7F;
+
+/// A firmware image on its way in, kept across disconnects so the app can
+/// resume the transfer.
+struct IncomingImage {
+    size: u32,
+    checksum: u32,
+    data: Vec<u8>,
+}
+
+/// Protocol side of the emulated mixer: slider positions and the replies to
+/// whatever the app asks for.
+pub struct Device {
+    info: DeviceInfo,
+    /// Where SaveConfig writes `info`, if anywhere.
+    flash: Option<PathBuf>,
+    capabilities: Capabilities,
+    /// Slider positions at full resolution.
+    positions: Vec<u16>,
+    /// Mute LED of every slider.
+    muted: Vec<bool>,
+    firmware_version: FirmwareVersion,
+    image: Option<IncomingImage>,
+    /// Set once an update checked out, the connection drops to boot it.
+    reboot: bool,
+    /// While set, frames from the app are swallowed without an answer.
+    frozen_until: Option<Instant>,
+    next_seq: u8,
+    codec: Codec,
+}
+
+impl Device {
+    pub fn new(info: DeviceInfo, flash: Option<PathBuf>, capabilities: Capabilities) -> Self {
+        Self {
+            flash,
+            positions: vec![percent_position(50); info.sliders.len()],
+            muted: vec![false; info.sliders.len()],
+            info,
+            capabilities,
+            firmware_version: FIRMWARE_VERSION,
+            image: None,
+            reboot: false,
+            frozen_until: None,
+            next_seq: 0,
+            codec: Codec::new(),
+        }
+    }
+
+    /// Handles a decoded frame from the app and returns the frames to write
+    /// back, starting with the acknowledgement.
+    pub fn handle_frame(&mut self, data: &[u8]) -> Vec<Vec<u8>> {
+        if self
+            .frozen_until
+            .is_some_and(|until| Instant::now() < until)
+        {
+            return Vec::new();
+        }
+
+        let (seq, payload) = match split_link_frame(data) {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("Rejecting frame from the app: {}", e);
+                return match data.first() {
+                    Some(&seq) => vec![self.frame(&[CommandIn::Nack as u8, seq])],
+                    None => Vec::new(),
+                };
+            }
+        };
+
+        let mut frames = vec![self.frame(&[CommandIn::Ack as u8, seq])];
+        let command = match self.codec.decode_out(payload) {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("Ignoring command from the app: {}", e);
+                return frames;
+            }
+        };
+        match &command {
+            CommandsOut::UpdateChunk(chunk) => println!(
+                "App sent UpdateChunk at {} ({} bytes)",
+                chunk.offset,
+                chunk.data.len()
+            ),
+            command => println!("App sent {:?}", command),
+        }
+
+        let reply = match command {
+            CommandsOut::Hello => Some(CommandsIn::Hello(HelloInfo {
+                protocol_version: PROTOCOL_VERSION,
+                firmware_version: self.firmware_version,
+                capabilities: self.capabilities,
+            })),
+            CommandsOut::RequestInfo => Some(CommandsIn::SendInfo(self.info.clone())),
+            CommandsOut::SetVolume(props) => {
+                if let Some(position) = self.positions.get_mut(props.channel as usize - 1) {
+                    *position = props.position();
+                }
+                None
+            }
+            CommandsOut::RequestVolume(channel) => self
+                .positions
+                .get(channel as usize - 1)
+                .map(|&position| CommandsIn::SendVolume(self.volume_info(channel, position))),
+            CommandsOut::RequestVolumes => Some(self.all_volumes()),
+            CommandsOut::SetVolumes(volumes) => {
+                let positions = volumes.into_iter().map(percent_position).collect();
+                self.set_positions(positions);
+                None
+            }
+            CommandsOut::SetPositions(positions) => {
+                self.set_positions(positions);
+                None
+            }
+            CommandsOut::SetMute(props) => {
+                if let Some(muted) = self.muted.get_mut(props.channel as usize - 1) {
+                    *muted = props.muted;
+                    let led = if props.muted { "on" } else { "off" };
+                    println!("Mute LED of slider {} {}", props.channel, led);
+                }
+                None
+            }
+            CommandsOut::SetDisplay(props) => {
+                println!(
+                    "Display of slider {} shows \"{}\" at {}%{}",
+                    props.channel,
+                    props.label,
+                    props.percentage,
+                    if props.icon.is_some() {
+                        " with an icon"
+                    } else {
+                        ""
+                    }
+                );
+                None
+            }
+            CommandsOut::RenameSlider(props) => {
+                if let Some(slider) = self.info.sliders.get_mut(props.channel as usize - 1) {
+                    slider.name = props.name;
+                }
+                None
+            }
+            CommandsOut::SetSliderAction(props) => {
+                if let Some(slider) = self.info.sliders.get_mut(props.channel as usize - 1) {
+                    slider.set_volume_action = props.action;
+                }
+                None
+            }
+            CommandsOut::SaveConfig => {
+                self.save_config();
+                None
+            }
+            CommandsOut::StartUpdate(props) => {
+                let resume = self.image.as_ref().is_some_and(|image| {
+                    image.size == props.size && image.checksum == props.checksum
+                });
+                if !resume {
+                    self.image = Some(IncomingImage {
+                        size: props.size,
+                        checksum: props.checksum,
+                        data: Vec::new(),
+                    });
+                }
+                let received = self.image.as_ref().map_or(0, |image| image.data.len());
+                Some(CommandsIn::UpdateProgress(received as u32))
+            }
+            CommandsOut::UpdateChunk(props) => {
+                Some(CommandsIn::UpdateProgress(self.receive_chunk(props)))
+            }
+            CommandsOut::FinishUpdate => Some(CommandsIn::UpdateFinished(self.finish_update())),
+            CommandsOut::Ping(nonce) => Some(CommandsIn::Pong(nonce)),
+            CommandsOut::SetLed(props) => {
+                println!(
+                    "LED of slider {} is {} at brightness {}",
+                    props.channel, props.color, props.brightness
+                );
+                None
+            }
+        };
+
+        if let Some(reply) = reply {
+            let payload = self.codec.encode_in(reply);
+            frames.push(self.frame(&payload));
+        }
+        frames
+    }
+
+    /// Applies a bulk set from the app, which has to cover every slider.
+    fn set_positions(&mut self, positions: Vec<u16>) {
+        if positions.len() != self.positions.len() {
+            eprintln!(
+                "Ignoring {} positions for {} sliders",
+                positions.len(),
+                self.positions.len()
+            );
+            return;
+        }
+        self.positions = positions;
+    }
+
+    /// Stops answering for `duration` while keeping the port open, like
+    /// firmware stuck in a loop.
+    pub fn freeze(&mut self, duration: Duration) {
+        self.frozen_until = Some(Instant::now() + duration);
+    }
+
+    /// Whether the app installed new firmware that should be booted now.
+    pub fn take_reboot(&mut self) -> bool {
+        std::mem::take(&mut self.reboot)
+    }
+
+    pub fn rebooting(&self) -> bool {
+        self.reboot
+    }
+
+    /// Stores a chunk if it is the next one and intact, returning how much
+    /// of the image is held.
+    fn receive_chunk(&mut self, chunk: UpdateChunkProps) -> u32 {
+        let Some(image) = &mut self.image else {
+            eprintln!("Chunk at {} without an update in progress", chunk.offset);
+            return 0;
+        };
+
+        let received = image.data.len();
+        if chunk.offset as usize != received {
+            eprintln!("Expected a chunk at {}, got {}", received, chunk.offset);
+        } else if crc16(&chunk.data) != chunk.crc {
+            eprintln!("Chunk at {} is corrupted", chunk.offset);
+        } else if received + chunk.data.len() > image.size as usize {
+            eprintln!("Chunk at {} overflows the image", chunk.offset);
+        } else {
+            image.data.extend_from_slice(&chunk.data);
+        }
+        image.data.len() as u32
+    }
+
+    fn finish_update(&mut self) -> bool {
+        let Some(image) = self.image.take() else {
+            eprintln!("Nothing to install");
+            return false;
+        };
+        if image.data.len() != image.size as usize || crc32(&image.data) != image.checksum {
+            eprintln!("The received image does not match its checksum");
+            return false;
+        }
+
+        match FirmwareImage::parse(image.data) {
+            Ok(firmware) => {
+                println!("Rebooting into firmware {}", firmware.version);
+                self.firmware_version = firmware.version;
+                self.reboot = true;
+                true
+            }
+            Err(e) => {
+                eprintln!("Refusing the image: {}", e);
+                false
+            }
+        }
+    }
+
+    fn save_config(&self) {
+        let Some(flash) = &self.flash else {
+            println!("No --info file to save the configuration to");
+            return;
+        };
+        let saved = serde_json::to_vec_pretty(&self.info)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(flash, json).map_err(|e| e.to_string()));
+        match saved {
+            Ok(()) => println!("Saved the configuration to {}", flash.display()),
+            Err(e) => eprintln!("Failed to save to {}: {}", flash.display(), e),
+        }
+    }
+
+    /// Moves a 1-based slider as if it was turned by hand, `None` if there is
+    /// no such slider.
+    pub fn move_slider(&mut self, channel: u8, position: u16) -> Option<Vec<u8>> {
+        let slot = self.positions.get_mut((channel as usize).checked_sub(1)?)?;
+        *slot = position;
+        let volume_info = self.volume_info(channel, position);
+        let payload = self.codec.encode_in(CommandsIn::SendVolume(volume_info));
+        Some(self.frame(&payload))
+    }
+
+    /// Moves every slider at once, starting with slider 1, `None` if there
+    /// are more values than sliders. Reported in a single frame when the
+    /// device announces [`Capabilities::BULK`], otherwise slider by slider.
+    pub fn move_sliders(&mut self, positions: &[u16]) -> Option<Vec<Vec<u8>>> {
+        if positions.len() > self.positions.len() {
+            return None;
+        }
+        if !self.capabilities.contains(Capabilities::BULK) {
+            return (1..)
+                .zip(positions)
+                .map(|(channel, &position)| self.move_slider(channel, position))
+                .collect();
+        }
+
+        self.positions[..positions.len()].copy_from_slice(positions);
+        let payload = self.codec.encode_in(self.all_volumes());
+        Some(vec![self.frame(&payload)])
+    }
+
+    /// How a slider at `position` is reported, with the fine position only
+    /// when high resolution is announced.
+    fn volume_info(&self, channel: u8, position: u16) -> VolumeInfo {
+        VolumeInfo {
+            channel,
+            volume: position_percent(position),
+            fine_position: self
+                .capabilities
+                .contains(Capabilities::HIGH_RES)
+                .then_some(position),
+        }
+    }
+
+    /// Every slider in a single bulk frame.
+    fn all_volumes(&self) -> CommandsIn {
+        if self.capabilities.contains(Capabilities::HIGH_RES) {
+            CommandsIn::SendPositions(self.positions.clone())
+        } else {
+            CommandsIn::SendVolumes(
+                self.positions
+                    .iter()
+                    .copied()
+                    .map(position_percent)
+                    .collect(),
+            )
+        }
+    }
+
+    /// Reports a 1-based button going down, up or being held, `None` if
+    /// there is no such button.
+    pub fn button_event(&mut self, button: u8, kind: ButtonEventKind) -> Option<Vec<u8>> {
+        self.info.buttons.get((button as usize).checked_sub(1)?)?;
+        let payload = self
+            .codec
+            .encode_in(CommandsIn::Button(ButtonEvent { button, kind }));
+        Some(self.frame(&payload))
+    }
+
+    /// Reports a 1-based encoder turned by `steps` detents, `None` if there
+    /// is no such encoder.
+    pub fn turn_encoder(&mut self, encoder: u8, steps: i8) -> Option<Vec<u8>> {
+        self.info.encoders.get((encoder as usize).checked_sub(1)?)?;
+        let payload = self
+            .codec
+            .encode_in(CommandsIn::Encoder(EncoderEvent { encoder, steps }));
+        Some(self.frame(&payload))
+    }
+
+    /// Bytes that are not valid COBS: the code byte promises more data than
+    /// arrives before the delimiter.
+    pub fn garbage_frame(&self) -> Vec<u8> {
+        vec![0x05, 0x01, FRAME_DELIMITER]
+    }
+
+    /// A volume report whose checksum does not match.
+    pub fn corrupt_frame(&mut self) -> Vec<u8> {
+        let mut data = vec![self.take_seq(), CommandIn::SendVolume as u8, 1, 50];
+        let crc = !crc16(&data);
+        data.extend_from_slice(&crc.to_le_bytes());
+        encode_frame(&data)
+    }
+
+    /// A well formed frame carrying a command the app does not know.
+    pub fn unknown_frame(&mut self) -> Vec<u8> {
+        self.frame(&[UNKNOWN_COMMAND, 0])
+    }
+
+    fn frame(&mut self, payload: &[u8]) -> Vec<u8> {
+        let seq = self.take_seq();
+        link_frame(seq, payload)
+    }
+
+    fn take_seq(&mut self) -> u8 {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use audiomixer_app2::protocol::{DeviceSliderData, SetVolumeProps, decode_frame};
+
+    use super::*;
+
+    fn device(capabilities: Capabilities) -> Device {
+        let slider = |name: &str| DeviceSliderData {
+            name: name.to_string(),
+            set_volume_action: String::new(),
+        };
+        let info = DeviceInfo {
+            sliders: vec![slider("One"), slider("Two"), slider("Three")],
+            buttons: Vec::new(),
+            encoders: Vec::new(),
+        };
+        Device::new(info, None, capabilities)
+    }
+
+    /// A decoded frame as the app sends it.
+    fn from_app(seq: u8, command: CommandsOut) -> Vec<u8> {
+        let mut data = vec![seq];
+        data.extend(Codec::new().encode(command));
+        data.extend_from_slice(&crc16(&data).to_le_bytes());
+        data
+    }
+
+    /// Payload of an encoded frame written back to the app.
+    fn payload(frame: &[u8]) -> Vec<u8> {
+        assert_eq!(frame.last(), Some(&FRAME_DELIMITER));
+        let data = decode_frame(&frame[..frame.len() - 1]).unwrap();
+        split_link_frame(&data).unwrap().1.to_vec()
+    }
+
+    /// Sends `command` and returns the reply after checking the
+    /// acknowledgement.
+    fn request(device: &mut Device, seq: u8, command: CommandsOut) -> Option<CommandsIn> {
+        let frames = device.handle_frame(&from_app(seq, command));
+        assert_eq!(payload(&frames[0]), [CommandIn::Ack as u8, seq]);
+        assert!(frames.len() <= 2, "more than one reply");
+        frames
+            .get(1)
+            .map(|frame| Codec::new().decode(&payload(frame)).unwrap())
+    }
+
+    #[test]
+    fn connect_sync_and_volume_flow() {
+        let capabilities = Capabilities::BULK | Capabilities::HIGH_RES;
+        let mut device = device(capabilities);
+
+        assert_eq!(
+            request(&mut device, 0, CommandsOut::Hello),
+            Some(CommandsIn::Hello(HelloInfo {
+                protocol_version: PROTOCOL_VERSION,
+                firmware_version: FIRMWARE_VERSION,
+                capabilities,
+            }))
+        );
+        let Some(CommandsIn::SendInfo(info)) = request(&mut device, 1, CommandsOut::RequestInfo)
+        else {
+            panic!("no device info");
+        };
+        assert_eq!(info.sliders.len(), 3);
+        assert_eq!(
+            request(&mut device, 2, CommandsOut::RequestVolumes),
+            Some(CommandsIn::SendPositions(vec![percent_position(50); 3]))
+        );
+
+        // The app moves a slider on screen
+        let props = SetVolumeProps::new(2, 12345, true);
+        assert_eq!(request(&mut device, 3, CommandsOut::SetVolume(props)), None);
+        assert_eq!(
+            request(&mut device, 4, CommandsOut::RequestVolume(2)),
+            Some(CommandsIn::SendVolume(VolumeInfo {
+                channel: 2,
+                volume: position_percent(12345),
+                fine_position: Some(12345),
+            }))
+        );
+
+        // And the user moves faders by hand
+        let frame = device.move_slider(1, 1000).unwrap();
+        assert_eq!(
+            Codec::new().decode(&payload(&frame)).unwrap(),
+            CommandsIn::SendVolume(VolumeInfo {
+                channel: 1,
+                volume: position_percent(1000),
+                fine_position: Some(1000),
+            })
+        );
+        let frames = device.move_sliders(&[1, 2, 3]).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            Codec::new().decode(&payload(&frames[0])).unwrap(),
+            CommandsIn::SendPositions(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn plain_firmware_reports_percents_one_by_one() {
+        let mut device = device(Capabilities::default());
+        assert_eq!(
+            request(&mut device, 0, CommandsOut::RequestVolume(1)),
+            Some(CommandsIn::SendVolume(VolumeInfo {
+                channel: 1,
+                volume: 50,
+                fine_position: None,
+            }))
+        );
+
+        let frames = device
+            .move_sliders(&[percent_position(10), percent_position(20)])
+            .unwrap();
+        let volumes = frames
+            .iter()
+            .map(|frame| Codec::new().decode(&payload(frame)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            volumes,
+            [
+                CommandsIn::SendVolume(VolumeInfo {
+                    channel: 1,
+                    volume: 10,
+                    fine_position: None,
+                }),
+                CommandsIn::SendVolume(VolumeInfo {
+                    channel: 2,
+                    volume: 20,
+                    fine_position: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn bulk_sets_cover_every_slider() {
+        let mut device = device(Capabilities::BULK | Capabilities::HIGH_RES);
+        assert_eq!(
+            request(&mut device, 0, CommandsOut::SetPositions(vec![1, 2, 3])),
+            None
+        );
+        assert_eq!(
+            request(&mut device, 1, CommandsOut::RequestVolumes),
+            Some(CommandsIn::SendPositions(vec![1, 2, 3]))
+        );
+
+        assert_eq!(
+            request(&mut device, 2, CommandsOut::SetVolumes(vec![0, 50, 100])),
+            None
+        );
+        let expected = [0, 50, 100].map(percent_position).to_vec();
+        assert_eq!(
+            request(&mut device, 3, CommandsOut::RequestVolumes),
+            Some(CommandsIn::SendPositions(expected.clone()))
+        );
+
+        // Too few or too many values leave the sliders where they are
+        request(&mut device, 4, CommandsOut::SetPositions(vec![7, 7]));
+        request(&mut device, 5, CommandsOut::SetVolumes(vec![7; 4]));
+        assert_eq!(
+            request(&mut device, 6, CommandsOut::RequestVolumes),
+            Some(CommandsIn::SendPositions(expected))
+        );
+    }
+
+    #[test]
+    fn unknown_sliders_are_refused() {
+        let mut device = device(Capabilities::default());
+        assert!(device.move_slider(0, 0).is_none());
+        assert!(device.move_slider(4, 0).is_none());
+        assert!(device.move_sliders(&[0; 4]).is_none());
+        assert_eq!(request(&mut device, 0, CommandsOut::RequestVolume(4)), None);
+    }
+
+    #[test]
+    fn broken_frames_are_nacked_or_ignored() {
+        let mut device = device(Capabilities::default());
+
+        let mut corrupted = from_app(7, CommandsOut::Hello);
+        corrupted[1] ^= 0xFF;
+        let frames = device.handle_frame(&corrupted);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(payload(&frames[0]), [CommandIn::Nack as u8, 7]);
+
+        // Intact but meaningless: acknowledged, then dropped
+        let mut data = vec![8, UNKNOWN_COMMAND];
+        data.extend_from_slice(&crc16(&data).to_le_bytes());
+        let frames = device.handle_frame(&data);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(payload(&frames[0]), [CommandIn::Ack as u8, 8]);
+    }
+
+    #[test]
+    fn frozen_device_stays_silent() {
+        let mut device = device(Capabilities::default());
+        device.freeze(Duration::from_secs(60));
+        assert!(
+            device
+                .handle_frame(&from_app(0, CommandsOut::Hello))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,271 @@
+//! Pretends to be a mixer on a pseudo-terminal, so the app can be run and
+//! tested without the hardware.
+//!
+//! ```text
+//! cargo run --bin emulator -- --script moves.txt
+//! AUDIOMIXER_PORT=/tmp/audiomixer-emulator cargo run
+//! ```
+
+#[cfg(unix)]
+mod connection;
+#[cfg(unix)]
+mod device;
+#[cfg(unix)]
+mod script;
+
+#[cfg(unix)]
+fn main() {
+    if let Err(e) = emulator::run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("The emulator needs a Unix pseudo-terminal");
+    std::process::exit(1);
+}
+
+#[cfg(unix)]
+mod emulator {
+    use std::{
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use audiomixer_app2::protocol::{
+        ButtonEventKind, Capabilities, DeviceButtonData, DeviceEncoderData, DeviceInfo,
+        DeviceSliderData,
+    };
+
+    use crate::{
+        connection::Connection,
+        device::Device,
+        script::{Step, parse_script},
+    };
+
+    const USAGE: &str = "\
+Usage: emulator [options]
+
+    --info <file>          DeviceInfo JSON sent in reply to RequestInfo, SaveConfig
+                           writes the slider configuration back to it
+    --script <file>        steps to play once the pty is up, one per line:
+                             wait <ms>, volume <channel> <value>, volumes <value>...,
+                             sweep <channel> <from> <to> <ms>, disconnect <ms>,
+                             freeze <ms>, press <button>, hold <button> <ms>,
+                             turn <encoder> <steps>, garbage, corrupt, unknown
+    --link <path>          stable path pointing to the pty (default /tmp/audiomixer-emulator)
+    --capabilities <bits>  capabilities announced in the Hello reply (default 0)";
+
+    /// How often a sweep reports the slider position.
+    const SWEEP_INTERVAL: Duration = Duration::from_millis(20);
+    /// How long the emulated device stays away to boot new firmware.
+    const REBOOT_TIME: Duration = Duration::from_secs(1);
+    /// How long a button has to be held before it reports a long press.
+    const LONG_PRESS: Duration = Duration::from_millis(500);
+
+    struct Options {
+        info: DeviceInfo,
+        info_path: Option<PathBuf>,
+        script: Vec<Step>,
+        link: PathBuf,
+        capabilities: Capabilities,
+    }
+
+    fn default_info() -> DeviceInfo {
+        let slider = |name: &str, set_volume_action: &str| DeviceSliderData {
+            name: name.to_string(),
+            set_volume_action: set_volume_action.to_string(),
+        };
+        let button = |name: &str, action: &str, long_press_action: &str| DeviceButtonData {
+            name: name.to_string(),
+            action: action.to_string(),
+            long_press_action: long_press_action.to_string(),
+        };
+        DeviceInfo {
+            sliders: vec![
+                slider("Master", "master"),
+                slider("Music", "app:spotify"),
+                slider("Mic", "source:default"),
+            ],
+            buttons: vec![
+                button("Master", "mute:1", ""),
+                button("Music", "play-pause", "next"),
+                button("Mic", "mute:3", ""),
+            ],
+            encoders: vec![DeviceEncoderData {
+                name: "Jog".to_string(),
+                action: "volume:1".to_string(),
+            }],
+        }
+    }
+
+    fn parse_args() -> Result<Options, String> {
+        let mut options = Options {
+            info: default_info(),
+            info_path: None,
+            script: Vec::new(),
+            link: PathBuf::from("/tmp/audiomixer-emulator"),
+            capabilities: Capabilities::default(),
+        };
+
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--info" => {
+                    let path = value()?;
+                    let raw = std::fs::read(&path).map_err(|e| format!("{path}: {e}"))?;
+                    options.info =
+                        serde_json::from_slice(&raw).map_err(|e| format!("{path}: {e}"))?;
+                    options.info_path = Some(PathBuf::from(path));
+                }
+                "--script" => {
+                    let path = value()?;
+                    let source =
+                        std::fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+                    options.script = parse_script(&source).map_err(|e| format!("{path}: {e}"))?;
+                }
+                "--link" => options.link = PathBuf::from(value()?),
+                "--capabilities" => {
+                    let bits = value()?;
+                    options.capabilities = Capabilities(
+                        bits.parse()
+                            .map_err(|_| format!("invalid capabilities {bits}"))?,
+                    );
+                }
+                "--help" | "-h" => return Err(USAGE.to_string()),
+                _ => return Err(format!("unknown argument {arg}\n\n{USAGE}")),
+            }
+        }
+
+        Ok(options)
+    }
+
+    pub fn run() -> Result<(), String> {
+        let options = parse_args()?;
+        let device = Arc::new(Mutex::new(Device::new(
+            options.info,
+            options.info_path,
+            options.capabilities,
+        )));
+        let mut steps = options.script.into_iter();
+
+        loop {
+            let connection = Connection::open(&options.link, device.clone())
+                .map_err(|e| format!("Failed to create the pty: {e}"))?;
+
+            let mut unplugged_for = None;
+            for step in steps.by_ref() {
+                if let Step::Disconnect(duration) = step {
+                    unplugged_for = Some(duration);
+                    break;
+                }
+                run_step(step, &connection, &device);
+            }
+
+            match unplugged_for {
+                Some(duration) => {
+                    connection.close();
+                    println!("Unplugged for {:?}", duration);
+                    thread::sleep(duration);
+                }
+                None => {
+                    println!("Script done, still answering the app");
+                    connection.wait();
+                    if !device.lock().unwrap().take_reboot() {
+                        return Ok(());
+                    }
+                    thread::sleep(REBOOT_TIME);
+                }
+            }
+        }
+    }
+
+    fn run_step(step: Step, connection: &Connection, device: &Mutex<Device>) {
+        println!("Running {:?}", step);
+        match step {
+            Step::Wait(duration) => thread::sleep(duration),
+            Step::Volume { channel, position } => {
+                move_slider(connection, device, channel, position)
+            }
+            Step::Volumes(volumes) => match device.lock().unwrap().move_sliders(&volumes) {
+                Some(frames) => {
+                    for frame in frames {
+                        connection.write(&frame);
+                    }
+                }
+                None => eprintln!("There are fewer than {} sliders", volumes.len()),
+            },
+            Step::Sweep {
+                channel,
+                from,
+                to,
+                duration,
+            } => {
+                let steps = (duration.as_millis() / SWEEP_INTERVAL.as_millis()).max(1) as i64;
+                let mut last = None;
+                for step in 0..=steps {
+                    let position = (from as i64 + (to as i64 - from as i64) * step / steps) as u16;
+                    if last != Some(position) {
+                        move_slider(connection, device, channel, position);
+                        last = Some(position);
+                    }
+                    thread::sleep(SWEEP_INTERVAL);
+                }
+            }
+            Step::Press(button) => {
+                button_event(connection, device, button, ButtonEventKind::Press);
+                button_event(connection, device, button, ButtonEventKind::Release);
+            }
+            Step::Hold { button, duration } => {
+                button_event(connection, device, button, ButtonEventKind::Press);
+                if duration >= LONG_PRESS {
+                    thread::sleep(LONG_PRESS);
+                    button_event(connection, device, button, ButtonEventKind::LongPress);
+                    thread::sleep(duration - LONG_PRESS);
+                } else {
+                    thread::sleep(duration);
+                }
+                button_event(connection, device, button, ButtonEventKind::Release);
+            }
+            Step::Turn { encoder, steps } => {
+                match device.lock().unwrap().turn_encoder(encoder, steps) {
+                    Some(frame) => connection.write(&frame),
+                    None => eprintln!("There is no encoder {}", encoder),
+                }
+            }
+            Step::Freeze(duration) => {
+                device.lock().unwrap().freeze(duration);
+                thread::sleep(duration);
+            }
+            Step::Garbage => connection.write(&device.lock().unwrap().garbage_frame()),
+            Step::Corrupt => connection.write(&device.lock().unwrap().corrupt_frame()),
+            Step::Unknown => connection.write(&device.lock().unwrap().unknown_frame()),
+            // Handled by `run`, it needs to tear down the pty
+            Step::Disconnect(_) => {}
+        }
+    }
+
+    fn move_slider(connection: &Connection, device: &Mutex<Device>, channel: u8, position: u16) {
+        match device.lock().unwrap().move_slider(channel, position) {
+            Some(frame) => connection.write(&frame),
+            None => eprintln!("There is no slider {}", channel),
+        }
+    }
+
+    fn button_event(
+        connection: &Connection,
+        device: &Mutex<Device>,
+        button: u8,
+        kind: ButtonEventKind,
+    ) {
+        match device.lock().unwrap().button_event(button, kind) {
+            Some(frame) => connection.write(&frame),
+            None => eprintln!("There is no button {}", button),
+        }
+    }
+}
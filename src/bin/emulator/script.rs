@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use audiomixer_app2::protocol::MAX_POSITION;
+
+/// One line of a script, see [`parse_script`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Wait(Duration),
+    /// Positions are read as percents with decimals and kept at full
+    /// resolution.
+    Volume {
+        channel: u8,
+        position: u16,
+    },
+    /// Moves every slider at once, starting with slider 1.
+    Volumes(Vec<u16>),
+    Sweep {
+        channel: u8,
+        from: u16,
+        to: u16,
+        duration: Duration,
+    },
+    Disconnect(Duration),
+    /// Stays connected but ignores the app.
+    Freeze(Duration),
+    /// A short press: down then up.
+    Press(u8),
+    Hold {
+        button: u8,
+        duration: Duration,
+    },
+    Turn {
+        encoder: u8,
+        steps: i8,
+    },
+    Garbage,
+    Corrupt,
+    Unknown,
+}
+
+/// Parses a script, one step per line. `#` starts a comment.
+///
+/// ```text
+/// wait 500              # pause for 500 ms
+/// volume 1 40           # move slider 1 to 40
+/// volume 1 12.34        # move slider 1 to 12.34, with high resolution faders
+/// volumes 10 20 30      # move sliders 1, 2 and 3 at the same time
+/// sweep 2 0 100 2000    # move slider 2 from 0 to 100 over 2 s
+/// disconnect 3000       # unplug, come back after 3 s
+/// freeze 5000           # ignore the app for 5 s without unplugging
+/// press 1               # press and release button 1
+/// hold 2 1000           # hold button 2 down for 1 s
+/// turn 1 -3             # turn encoder 1 three detents counterclockwise
+/// garbage               # bytes that are not valid COBS
+/// corrupt               # a frame with a wrong checksum
+/// unknown               # a frame with an unknown command
+/// ```
+pub fn parse_script(source: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+
+    for (index, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let step = parse_step(&words).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        steps.push(step);
+    }
+
+    Ok(steps)
+}
+
+fn parse_step(words: &[&str]) -> Result<Step, String> {
+    let step = match words {
+        ["wait", ms] => Step::Wait(millis(ms)?),
+        ["volume", channel, volume] => Step::Volume {
+            channel: number(channel)?,
+            position: position(volume)?,
+        },
+        ["volumes", volumes @ ..] if !volumes.is_empty() => Step::Volumes(
+            volumes
+                .iter()
+                .map(|volume| position(volume))
+                .collect::<Result<_, _>>()?,
+        ),
+        ["sweep", channel, from, to, ms] => Step::Sweep {
+            channel: number(channel)?,
+            from: position(from)?,
+            to: position(to)?,
+            duration: millis(ms)?,
+        },
+        ["disconnect", ms] => Step::Disconnect(millis(ms)?),
+        ["freeze", ms] => Step::Freeze(millis(ms)?),
+        ["press", button] => Step::Press(number(button)?),
+        ["hold", button, ms] => Step::Hold {
+            button: number(button)?,
+            duration: millis(ms)?,
+        },
+        ["turn", encoder, steps] => Step::Turn {
+            encoder: number(encoder)?,
+            steps: steps
+                .parse()
+                .map_err(|_| format!("\"{steps}\" is not a number between -128 and 127"))?,
+        },
+        ["garbage"] => Step::Garbage,
+        ["corrupt"] => Step::Corrupt,
+        ["unknown"] => Step::Unknown,
+        _ => return Err(format!("cannot understand \"{}\"", words.join(" "))),
+    };
+    Ok(step)
+}
+
+fn number(word: &str) -> Result<u8, String> {
+    word.parse()
+        .map_err(|_| format!("\"{word}\" is not a number between 0 and 255"))
+}
+
+/// A percentage, decimals allowed, as a fader position.
+fn position(word: &str) -> Result<u16, String> {
+    match word.parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => {
+            Ok((percent / 100.0 * MAX_POSITION as f64).round() as u16)
+        }
+        _ => Err(format!("\"{word}\" is not between 0 and 100")),
+    }
+}
+
+fn millis(word: &str) -> Result<Duration, String> {
+    word.parse()
+        .map(Duration::from_millis)
+        .map_err(|_| format!("\"{word}\" is not a number of milliseconds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_step() {
+        let script = "\
+# warm up
+wait 500
+
+volume 1 40           # trailing comment
+volume 2 12.5
+volumes 10 20 30
+sweep 2 0 100 2000
+disconnect 3000
+freeze 5000
+press 1
+hold 2 1000
+turn 1 -3
+garbage
+corrupt
+unknown
+";
+        let percent = |percent: f64| (percent / 100.0 * MAX_POSITION as f64).round() as u16;
+        assert_eq!(
+            parse_script(script),
+            Ok(vec![
+                Step::Wait(Duration::from_millis(500)),
+                Step::Volume {
+                    channel: 1,
+                    position: percent(40.0),
+                },
+                Step::Volume {
+                    channel: 2,
+                    position: percent(12.5),
+                },
+                Step::Volumes(vec![percent(10.0), percent(20.0), percent(30.0)]),
+                Step::Sweep {
+                    channel: 2,
+                    from: 0,
+                    to: MAX_POSITION,
+                    duration: Duration::from_secs(2),
+                },
+                Step::Disconnect(Duration::from_secs(3)),
+                Step::Freeze(Duration::from_secs(5)),
+                Step::Press(1),
+                Step::Hold {
+                    button: 2,
+                    duration: Duration::from_secs(1),
+                },
+                Step::Turn {
+                    encoder: 1,
+                    steps: -3,
+                },
+                Step::Garbage,
+                Step::Corrupt,
+                Step::Unknown,
+            ])
+        );
+        assert_eq!(parse_script("# nothing\n\n   \n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let cases = [
+            ("jump 3", "line 1: cannot understand \"jump 3\""),
+            ("wait", "line 1: cannot understand \"wait\""),
+            ("volumes", "line 1: cannot understand \"volumes\""),
+            ("press 1 2", "line 1: cannot understand \"press 1 2\""),
+            (
+                "wait 10\nwait soon",
+                "line 2: \"soon\" is not a number of milliseconds",
+            ),
+            ("wait -5", "line 1: \"-5\" is not a number of milliseconds"),
+            ("volume 1 101", "line 1: \"101\" is not between 0 and 100"),
+            ("volumes 10 -1", "line 1: \"-1\" is not between 0 and 100"),
+            (
+                "volume 256 10",
+                "line 1: \"256\" is not a number between 0 and 255",
+            ),
+            (
+                "# comment\n\nturn 1 200",
+                "line 3: \"200\" is not a number between -128 and 127",
+            ),
+        ];
+        for (script, error) in cases {
+            assert_eq!(parse_script(script), Err(error.to_string()), "{script:?}");
+        }
+    }
+}
@@ -1,2 +1,4 @@
+mod permission_help;
+pub use permission_help::*;
 mod slider;
 pub use slider::*;
@@ -0,0 +1,86 @@
+use freya::{prelude::*, radio::use_radio};
+
+use crate::{
+    Data, DataChannel,
+    utils::{UDEV_RULE_FILE, udev_rule, write_udev_rule},
+};
+
+/// Explains how to get access to the serial port after opening it failed
+/// with EACCES.
+#[derive(PartialEq)]
+pub struct PermissionHelp {
+    port: String,
+}
+
+impl PermissionHelp {
+    pub fn new(port: impl Into<String>) -> Self {
+        Self { port: port.into() }
+    }
+}
+
+impl Component for PermissionHelp {
+    fn render(&self) -> impl IntoElement {
+        let radio = use_radio::<Data, DataChannel>(DataChannel::NoUpdate);
+        let matchers = radio.read().config.matchers.clone();
+        let mut show_rule = use_state(|| false);
+        let mut saved = use_state(|| None::<String>);
+
+        let mut children = vec![
+            label()
+                .text(format!("Your user is not allowed to open {}.", self.port))
+                .into(),
+            label()
+                .text(
+                    "Serial ports usually belong to the dialout group (uucp on Arch). \
+                     Add yourself with `sudo usermod -aG dialout $USER` and log in again, \
+                     or install a udev rule for the mixer:",
+                )
+                .into(),
+            rect()
+                .direction(Direction::Horizontal)
+                .spacing(8.0)
+                .children([
+                    Button::new()
+                        .on_press(move |_| {
+                            let shown = show_rule();
+                            show_rule.set(!shown);
+                        })
+                        .child(if show_rule() {
+                            "Hide udev rule"
+                        } else {
+                            "Show udev rule"
+                        })
+                        .into(),
+                    Button::new()
+                        .on_press({
+                            let matchers = matchers.clone();
+                            move |_| {
+                                saved.set(Some(match write_udev_rule(&matchers) {
+                                    Ok(path) => format!(
+                                        "Saved to {0}, install it with: sudo cp {0} /etc/udev/rules.d/ \
+                                         && sudo udevadm control --reload-rules && sudo udevadm trigger",
+                                        path.display()
+                                    ),
+                                    Err(e) => format!("Failed to write {}: {}", UDEV_RULE_FILE, e),
+                                }));
+                            }
+                        })
+                        .child("Save udev rule")
+                        .into(),
+                ])
+                .into(),
+        ];
+
+        if show_rule() {
+            children.push(label().text(udev_rule(&matchers)).into());
+        }
+        if let Some(saved) = saved.read().clone() {
+            children.push(label().text(saved).into());
+        }
+
+        rect()
+            .width(Size::percent(80.0))
+            .spacing(8.0)
+            .children(children)
+    }
+}
@@ -2,14 +2,22 @@ use freya::components::Slider as FreyaSlider;
 use freya::prelude::*;
 use std::borrow::Cow;
 
+use crate::utils::MAX_POSITION;
+
+/// Half a fader position in percent. Smaller changes are the echo of a value
+/// that was just set, not a move.
+const VALUE_TOLERANCE: f64 = 50.0 / MAX_POSITION as f64;
+
 #[derive(PartialEq)]
 pub struct Slider {
     title: Cow<'static, str>,
     width: Size,
 
     value: f64,
+    muted: bool,
 
     on_changed: Option<EventHandler<f64>>,
+    on_toggle_mute: Option<EventHandler<()>>,
 }
 
 impl Slider {
@@ -18,7 +26,9 @@ impl Slider {
             title: Cow::from("Slider"),
             width: Size::default(),
             on_changed: None,
+            on_toggle_mute: None,
             value: 50.0,
+            muted: false,
         }
     }
 
@@ -41,12 +51,43 @@ impl Slider {
         self.value = value.clamp(0.0, 100.0);
         self
     }
+
+    pub fn muted(mut self, muted: bool) -> Self {
+        self.muted = muted;
+        self
+    }
+
+    /// Shows a mute button under the value.
+    pub fn on_toggle_mute(mut self, on_toggle_mute: impl FnMut(()) + 'static) -> Self {
+        self.on_toggle_mute = Some(EventHandler::new(on_toggle_mute));
+        self
+    }
 }
 
 impl Component for Slider {
     fn render(&self) -> impl IntoElement {
         let mut value = use_reactive(&self.value);
 
+        let mut footer = vec![
+            label()
+                .font_size(36.0)
+                .font_weight(FontWeight::BOLD)
+                .text(if self.muted {
+                    "Muted".to_string()
+                } else {
+                    format!("{:.0}%", value())
+                })
+                .into(),
+        ];
+        if let Some(on_toggle_mute) = self.on_toggle_mute.clone() {
+            footer.push(
+                Button::new()
+                    .on_press(move |_| on_toggle_mute.call(()))
+                    .child(if self.muted { "Unmute" } else { "Mute" })
+                    .into(),
+            );
+        }
+
         rect()
             .height(Size::Fill)
             .width(self.width.clone())
@@ -72,9 +113,9 @@ impl Component for Slider {
                     .child(
                         FreyaSlider::new({
                             let on_changed = self.on_changed.clone();
-                            move |arg: f64| {
-                                let val = arg.round();
-                                if val != *value.read() {
+                            // Unrounded, so the fader keeps its full resolution
+                            move |val: f64| {
+                                if (val - *value.read()).abs() > VALUE_TOLERANCE {
                                     value.set(val);
                                     if let Some(on_changed) = &on_changed {
                                         on_changed.call(val);
@@ -94,12 +135,8 @@ impl Component for Slider {
                     .center()
                     .width(Size::Fill)
                     .padding(8.0)
-                    .child(
-                        label()
-                            .font_size(36.0)
-                            .font_weight(FontWeight::BOLD)
-                            .text(format!("{}%", value())),
-                    )
+                    .spacing(8.0)
+                    .children(footer)
                     .into(),
             ])
     }
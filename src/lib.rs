@@ -0,0 +1,3 @@
+//! Wire protocol of the mixer, shared by the app and its benchmarks.
+
+pub mod protocol;
@@ -6,21 +6,15 @@ use freya::{
     prelude::*,
     radio::{RadioChannel, RadioStation},
     tray::{
-        TrayEvent, TrayIconBuilder,
+        TrayEvent, TrayIcon, TrayIconBuilder,
         menu::{Menu, MenuEvent, MenuItem},
     },
 };
 
 use futures_channel::mpsc::UnboundedSender;
 use futures_lite::StreamExt;
-use serialport::{SerialPort, UsbPortInfo};
-use smol::Timer;
-use std::{
-    sync::{Arc, Mutex},
-    thread,
-    time::Duration,
-};
-use utils::send_command;
+use serialport::UsbPortInfo;
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc, thread, time::Instant};
 
 mod app;
 mod components;
@@ -29,26 +23,46 @@ mod utils;
 
 use app::App;
 
-use crate::utils::{CommandsIn, CommandsOut, find_serial_port, run_action};
+use crate::utils::{
+    ButtonAction, ButtonEvent, Capabilities, CommandsIn, CommandsOut, Config, ConnectionState,
+    DeviceId, EncoderAction, EncoderEvent, FirmwareImage, HelloInfo, LatencyStats, LedState,
+    LinkStats, MAX_POSITION, SetDisplayProps, SetMuteProps, SetVolumeProps, Uploader, VolumeAction,
+    feedback_ticks, handle_button, handle_encoder, handle_update_reply, image_loaded,
+    percent_position, position_percent, refresh_displays, refresh_leds, resume_stalled_update,
+    run_action, scan_devices, update_stalled,
+};
 
 const ICON: &[u8] = include_bytes!("./freya_icon.png");
-const TARGET_VID: u16 = 0x303a;
-const TARGET_PID: u16 = 0x8145;
+const MUTE_ALL_MENU_ID: &str = "mute-all";
 
 fn main() {
-    let mut radio_station = RadioStation::create_global(Data::default());
+    let mut radio_station = RadioStation::create_global(Data {
+        config: Config::load(),
+        ..Data::default()
+    });
+    // Kept around so the tooltip can follow the connection state
+    let tray: Rc<RefCell<Option<TrayIcon>>> = Rc::new(RefCell::new(None));
 
-    let tray_icon = || {
+    let tray_clone = tray.clone();
+    let tray_icon = move || {
         let tray_menu = Menu::new();
         let _ = tray_menu.append(&MenuItem::new("Open", true, None));
         let _ = tray_menu.append(&MenuItem::new("Add slider", true, None));
+        let _ = tray_menu.append(&MenuItem::with_id(
+            MUTE_ALL_MENU_ID,
+            "Mute/unmute all",
+            true,
+            None,
+        ));
         let _ = tray_menu.append(&MenuItem::new("Exit", true, None));
-        TrayIconBuilder::new()
+        let tray_icon = TrayIconBuilder::new()
             .with_menu(Box::new(tray_menu))
-            .with_tooltip("Freya Tray")
+            .with_tooltip(tray_tooltip(&Data::default()))
             .with_icon(LaunchConfig::tray_icon(ICON))
             .build()
-            .unwrap()
+            .unwrap();
+        tray_clone.replace(Some(tray_icon.clone()));
+        tray_icon
     };
     let tray_handler = move |ev, mut ctx: RendererContext| match ev {
         TrayEvent::Menu(MenuEvent { id }) if id == "3" => {
@@ -57,14 +71,39 @@ fn main() {
             );
         }
         TrayEvent::Menu(MenuEvent { id }) if id == "4" => {
-            radio_station
-                .write_channel(DataChannel::SlidersUpdate)
-                .sliders
-                .push(SliderData {
+            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+            if let Some(device) = data
+                .devices
+                .values_mut()
+                .find(|device| device.info.is_some())
+            {
+                device.sliders.push(SliderData {
                     name: "New slider".to_string(),
-                    volume: 50,
+                    position: percent_position(50),
+                    muted: false,
                     set_volume_action: VolumeAction::Print,
+                    device_action: String::new(),
                 });
+            }
+        }
+        TrayEvent::Menu(MenuEvent { id }) if id == MUTE_ALL_MENU_ID => {
+            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+            // Mute everything unless it already is, then unmute
+            let muted = data
+                .devices
+                .values()
+                .filter(|device| device.info.is_some())
+                .flat_map(|device| &device.sliders)
+                .all(|slider| slider.muted);
+            for device in data
+                .devices
+                .values_mut()
+                .filter(|device| device.info.is_some())
+            {
+                for index in 0..device.sliders.len() {
+                    device.set_muted(index, !muted);
+                }
+            }
         }
         TrayEvent::Menu(MenuEvent { id }) if id == "5" => {
             ctx.exit();
@@ -76,237 +115,198 @@ fn main() {
         LaunchConfig::new()
             .with_future(move |_| async move {
                 let (state_tx, mut state_rx) = futures_channel::mpsc::unbounded::<ChannelSend>();
-                let (serial_out_tx, mut serial_out_rx) =
-                    futures_channel::mpsc::unbounded::<CommandsOut>();
-                let serial_port: Arc<Mutex<Option<Box<dyn SerialPort>>>> =
-                    Arc::new(Mutex::new(None));
+                radio_station.write_channel(DataChannel::NoUpdate).state_tx =
+                    Some(state_tx.clone());
 
-                radio_station
-                    .write_channel(DataChannel::NoUpdate)
-                    .serial_out_tx = Some(serial_out_tx.clone());
-
-                let serial_port_clone = serial_port.clone();
+                let config = radio_station.read().config.clone();
+                let state_tx_clone = state_tx.clone();
+                thread::spawn(move || smol::block_on(scan_devices(config, state_tx_clone)));
                 let state_tx_clone = state_tx.clone();
-                thread::spawn(move || {
-                    smol::block_on(async {
-                        println!("Starting device scan...");
-                        loop {
-                            println!("Scanning for device...");
-                            match find_serial_port(TARGET_VID, TARGET_PID) {
-                                Ok(port_info) => {
-                                    println!("Device found! {}", port_info.0);
-                                    let port = serialport::new(&port_info.0, 115_200)
-                                        .timeout(Duration::from_millis(10))
-                                        .dtr_on_open(true)
-                                        .open()
-                                        .expect("Failed to open port");
-
-                                    // Send initial commands to get device info and volume states
-                                    println!("Requesting device info...");
-
-                                    serial_port_clone.lock().unwrap().replace(port);
-
-                                    state_tx_clone
-                                        .unbounded_send(ChannelSend::DeviceInfoUpdate(Some(
-                                            DeviceInfo {
-                                                usb_info: port_info.1,
-                                            },
-                                        )))
-                                        .expect("Failed to send device info");
-
-                                    state_tx_clone
-                                        .unbounded_send(ChannelSend::SlidersInfoUpdate(vec![
-                                            SliderData {
-                                                name: "Channel 1".to_string(),
-                                                volume: 0,
-                                                set_volume_action: VolumeAction::Print,
-                                            },
-                                            SliderData {
-                                                name: "Channel 2".to_string(),
-                                                volume: 0,
-                                                set_volume_action: VolumeAction::Print,
-                                            },
-                                        ]))
-                                        .expect("Failed to send sliders info");
-
-                                    break;
+                thread::spawn(move || smol::block_on(feedback_ticks(state_tx_clone)));
+
+                while let Some(channel_data) = state_rx.next().await {
+                    match channel_data {
+                        ChannelSend::SlidersInfoUpdate(id, mut sliders) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            let device = data.devices.entry(id).or_default();
+                            // Keep the bindings the user picked before a reconnect, unless
+                            // the device now declares a different target
+                            for (slider, previous) in sliders.iter_mut().zip(&device.sliders) {
+                                if slider.name == previous.name {
+                                    if slider.device_action == previous.device_action {
+                                        slider.set_volume_action =
+                                            previous.set_volume_action.clone();
+                                    }
+                                    slider.muted = previous.muted;
                                 }
-                                Err(e) => {
-                                    println!("Device not found! {}", e);
+                            }
+                            device.sliders = sliders;
+                            device.leds.clear();
+                            device.displays.clear();
+                            // The device forgets its mute LEDs while unplugged
+                            for (index, slider) in device.sliders.iter().enumerate() {
+                                if slider.muted {
+                                    device.send_command(CommandsOut::SetMute(SetMuteProps {
+                                        channel: index as u8 + 1,
+                                        muted: true,
+                                    }));
                                 }
-                            };
-                            Timer::after(Duration::from_secs(1)).await;
+                            }
                         }
-                    });
-                });
-
-                // Sender thread
-                let serial_port_clone_2 = serial_port.clone();
-                thread::spawn(move || {
-                    smol::block_on(async {
-                        loop {
-                            match serial_out_rx.try_next() {
-                                Ok(Some(command)) => {
-                                    if let Some(port) =
-                                        &mut serial_port_clone_2.lock().unwrap().as_mut()
-                                    {
-                                        println!("Sending command: {:?}", command);
-                                        port.write_all(&crate::utils::get_payload(command))
-                                            .unwrap();
-                                    }
+                        ChannelSend::ControlsInfoUpdate(id, mut buttons, mut encoders) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            let device = data.devices.entry(id).or_default();
+                            for (button, previous) in buttons.iter_mut().zip(&device.buttons) {
+                                if button.name == previous.name {
+                                    button.press = previous.press.clone();
+                                    button.long_press = previous.long_press.clone();
+                                }
+                            }
+                            for (encoder, previous) in encoders.iter_mut().zip(&device.encoders) {
+                                if encoder.name == previous.name {
+                                    encoder.action = previous.action.clone();
                                 }
-                                _ => continue,
                             }
+                            device.buttons = buttons;
+                            device.encoders = encoders;
                         }
-                    });
-                });
-
-                // Receiver thread
-                let serial_port_clone = serial_port.clone();
-                let state_tx_clone2 = state_tx.clone();
-                thread::spawn(move || {
-                    smol::block_on(async {
-                        let mut read_buffer: Vec<u8> = vec![0; 1024];
-                        let mut pending_buffer: Vec<u8> = Vec::new();
-                        let mut last_data_time = std::time::Instant::now();
-
-                        loop {
-                            if let Some(port) = &mut serial_port_clone
-                                .lock()
-                                .unwrap()
-                                .as_mut()
+                        ChannelSend::DeviceInfoUpdate(id, device_info) => {
+                            radio_station
+                                .write_channel(DataChannel::DeviceInfo)
+                                .devices
+                                .entry(id)
+                                .or_default()
+                                .info = device_info;
+                        }
+                        ChannelSend::HelloUpdate(id, hello) => {
+                            if let Some(device_info) = radio_station
+                                .write_channel(DataChannel::DeviceInfo)
+                                .devices
+                                .get_mut(&id)
+                                .and_then(|device| device.info.as_mut())
                             {
-                                match port.read(&mut read_buffer) {
-                                    Ok(n) if n > 0 => {
-                                        let received = &read_buffer[..n];
-                                        println!("Received command buffer: {:#04X?}", received);
-
-                                        // Append new data to pending buffer
-                                        pending_buffer.extend_from_slice(received);
-                                        last_data_time = std::time::Instant::now();
-                                    }
-                                    Ok(_) | Err(_)
-                                        if !pending_buffer.is_empty()
-                                            && last_data_time.elapsed() > Duration::from_millis(100) =>
-                                    {
-                                        // Timeout with pending data - process what we have
-                                        eprintln!(
-                                            "Warning: timeout with {} bytes pending, clearing buffer",
-                                            pending_buffer.len()
-                                        );
-                                        pending_buffer.clear();
-                                    }
-                                    Ok(_) => {
-                                        // Timeout - no data
-                                    }
-                                    Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => {
-                                        // Expected timeout, keep looping
-                                    }
-                                    Err(ref e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
-                                        // Device disconnected, reset state
-                                        let _ = state_tx_clone2.unbounded_send(ChannelSend::DeviceInfoUpdate(None));
-                                        continue;
-                                    }
-                                    Err(e) => {
-                                        eprintln!("Serial read error: {}", e);
-                                    }
-                                }
+                                device_info.hello = Some(hello);
+                            }
+                        }
+                        ChannelSend::ConnectionStateUpdate(id, state) => {
+                            let mut data =
+                                radio_station.write_channel(DataChannel::ConnectionState);
+                            if state.is_error() {
+                                data.last_error = Some(state.clone());
+                            } else if state == ConnectionState::Connected {
+                                data.last_error = None;
                             }
+                            if let Some(id) = id {
+                                data.devices.entry(id).or_default().connection_state =
+                                    state.clone();
+                            }
+                            data.connection_state = state;
 
-                            // Process complete packets (terminated by 0xFF)
-                            while let Some(terminator_pos) = pending_buffer
-                                .iter()
-                                .position(|&b| b == 0xFF)
+                            if let Some(tray) = tray.borrow().as_ref() {
+                                let _ = tray.set_tooltip(Some(tray_tooltip(&data)));
+                            }
+                        }
+                        ChannelSend::LinkStatsUpdate(id, link_stats) => {
+                            radio_station
+                                .write_channel(DataChannel::LinkStats)
+                                .devices
+                                .entry(id)
+                                .or_default()
+                                .link_stats = link_stats;
+                        }
+                        ChannelSend::LatencyUpdate(id, latency) => {
+                            radio_station
+                                .write_channel(DataChannel::LinkStats)
+                                .devices
+                                .entry(id)
+                                .or_default()
+                                .latency = latency;
+                        }
+                        ChannelSend::SliderVolumeUpdate(id, channel, position) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            match data
+                                .devices
+                                .get_mut(&id)
+                                .and_then(|device| device.sliders.get_mut(channel - 1))
                             {
-                                let packet: Vec<u8> = pending_buffer
-                                    .drain(..terminator_pos)
-                                    .collect();
-                                pending_buffer.drain(..1); // Remove the 0xFF terminator
-
-                                if !packet.is_empty() {
-                                    match crate::utils::get_received_payload(&packet) {
-                                        Ok(command) => {
-                                            println!("Received command: {:?}", command);
-                                            match command {
-                                                CommandsIn::SendInfo(device_info) => {
-                                                    println!("Received device info: {:?}", device_info);
-                                                    for (i, slider) in device_info
-                                                        .sliders
-                                                        .iter()
-                                                        .enumerate()
-                                                    {
-                                                        let _ = state_tx.unbounded_send(
-                                                            ChannelSend::SliderInfoUpdate(
-                                                                i + 1,
-                                                                SliderData {
-                                                                    name: slider
-                                                                        .name
-                                                                        .clone(),
-                                                                    volume: 0,
-                                                                    set_volume_action: VolumeAction::Print,
-                                                                },
-                                                            ),
-                                                        );
-                                                    }
-                                                }
-                                                CommandsIn::SendVolume(volume_info) => {
-                                                    println!("Received volume info: {:?}", volume_info);
-                                                    let _ = state_tx.unbounded_send(
-                                                        ChannelSend::SliderVolumeUpdate(
-                                                            volume_info.channel.into(),
-                                                            volume_info.volume,
-                                                        ),
-                                                    );
-                                                }
-                                            }
-                                        }
-                                        Err(e) => {
-                                            eprintln!("Failed to parse incoming payload: {}", e);
-                                        }
+                                Some(slider) => {
+                                    slider.position = position;
+                                    run_action(slider);
+                                }
+                                None => eprintln!("Volume update for unknown channel {}", channel),
+                            }
+                        }
+                        ChannelSend::SlidersVolumesUpdate(id, positions) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            if let Some(device) = data.devices.get_mut(&id) {
+                                // A bulk frame covers every channel, anything else is stale
+                                // or garbled
+                                if positions.len() != device.sliders.len() {
+                                    eprintln!(
+                                        "Dropping bulk update of {} channels for {} with {} sliders",
+                                        positions.len(),
+                                        id,
+                                        device.sliders.len()
+                                    );
+                                    continue;
+                                }
+                                // Only the faders that moved reach their targets
+                                for (slider, position) in device.sliders.iter_mut().zip(positions) {
+                                    if slider.position != position {
+                                        slider.position = position;
+                                        run_action(slider);
                                     }
                                 }
                             }
-
-                            Timer::after(Duration::from_millis(1)).await;
                         }
-                    });
-                });
+                        ChannelSend::FeedbackTick => {
+                            let now = Instant::now();
+                            let mut data = radio_station.write_channel(DataChannel::Feedback);
+                            for device in data.devices.values_mut() {
+                                refresh_leds(device);
+                                refresh_displays(device);
+                            }
+                            let stalled = data
+                                .devices
+                                .values()
+                                .any(|device| update_stalled(device, now));
+                            drop(data);
 
-                while let Some(channel_data) = state_rx.next().await {
-                    match channel_data {
-                        ChannelSend::SlidersInfoUpdate(sliders) => {
-                            radio_station
-                                .write_channel(DataChannel::SlidersUpdate)
-                                .sliders = sliders;
+                            if stalled {
+                                let mut data =
+                                    radio_station.write_channel(DataChannel::FirmwareUpdate);
+                                for device in data.devices.values_mut() {
+                                    resume_stalled_update(device, now);
+                                }
+                            }
                         }
-                        ChannelSend::SliderInfoUpdate(index, slider_data) => {
-                            radio_station
-                                .write_channel(DataChannel::SlidersUpdate)
-                                .sliders[index] = slider_data;
+                        ChannelSend::UpdateReply(id, reply) => {
+                            let mut data = radio_station.write_channel(DataChannel::FirmwareUpdate);
+                            if let Some(device) = data.devices.get_mut(&id) {
+                                handle_update_reply(device, &reply);
+                            }
                         }
-                        ChannelSend::DeviceInfoUpdate(device_info) => {
-                            // let router_context = RouterContext::get();
-                            // if router_context.current::<Route>() == Route::Loading
-                            //     && device_info.is_some()
-                            // {
-                            //     router_context.replace(Route::Main);
-                            // } else if router_context.current::<Route>() != Route::Loading
-                            //     && device_info.is_none()
-                            // {
-                            //     router_context.replace(Route::Loading);
-                            // }
-
-                            radio_station
-                                .write_channel(DataChannel::DeviceInfo)
-                                .device_info = device_info;
+                        ChannelSend::ImageLoaded(id, image) => {
+                            let mut data = radio_station.write_channel(DataChannel::FirmwareUpdate);
+                            if let Some(device) = data.devices.get_mut(&id) {
+                                image_loaded(device, image);
+                            }
+                        }
+                        ChannelSend::ButtonInput(id, event) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            let Data {
+                                devices, config, ..
+                            } = &mut *data;
+                            if let Some(device) = devices.get_mut(&id) {
+                                handle_button(device, &event, config);
+                            }
+                        }
+                        ChannelSend::EncoderInput(id, event) => {
+                            let mut data = radio_station.write_channel(DataChannel::SlidersUpdate);
+                            if let Some(device) = data.devices.get_mut(&id) {
+                                handle_encoder(device, &event);
+                            }
                         }
-                        ChannelSend::SliderVolumeUpdate(channel, volume) => {
-                            radio_station
-                                .write_channel(DataChannel::SlidersUpdate)
-                                .sliders[channel - 1]
-                                .volume = volume;
-                            run_action(&radio_station.read().sliders[channel - 1]);
-                        },
                     }
                 }
             })
@@ -317,40 +317,264 @@ fn main() {
 
 #[allow(dead_code)]
 pub struct DeviceInfo {
-    pub usb_info: UsbPortInfo,
+    pub port_name: String,
+    /// Missing when the port was forced through the config and is not USB.
+    pub usb_info: Option<UsbPortInfo>,
+    /// Filled in once the device answers the Hello handshake.
+    pub hello: Option<HelloInfo>,
+    pub serial_out_tx: UnboundedSender<CommandsOut>,
+}
+
+impl DeviceInfo {
+    /// Human readable name for the device.
+    pub fn name(&self) -> String {
+        match &self.usb_info {
+            Some(UsbPortInfo {
+                product: Some(product),
+                ..
+            }) => format!("{} ({})", product, self.port_name),
+            _ => self.port_name.clone(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct SliderData {
     pub name: String,
-    pub volume: u8,
+    /// Fader position up to [`utils::MAX_POSITION`], whole percents scaled
+    /// up for firmware without high resolution faders.
+    pub position: u16,
+    pub muted: bool,
     pub set_volume_action: VolumeAction,
+    /// Action string stored on the device, see [`utils::DeviceSliderData`].
+    pub device_action: String,
+}
+
+impl SliderData {
+    /// Whole percent, as shown in the app and on the device.
+    pub fn volume(&self) -> u8 {
+        position_percent(self.position)
+    }
+
+    /// Position from 0 to 1 at full resolution, what audio targets are set
+    /// to.
+    pub fn level(&self) -> f64 {
+        self.position as f64 / MAX_POSITION as f64
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ButtonData {
+    pub name: String,
+    pub press: ButtonAction,
+    pub long_press: ButtonAction,
+    /// Set once the current press turned into a long press, so the release
+    /// does not run the short press action too.
+    pub held_long: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
-pub enum VolumeAction {
-    Print,
+pub struct EncoderData {
+    pub name: String,
+    pub action: EncoderAction,
 }
 
+/// Everything known about one mixer. Entries are kept after the device
+/// disconnects so its settings survive a reconnect.
 #[derive(Default)]
-struct Data {
-    pub device_info: Option<DeviceInfo>,
+pub struct Device {
+    /// `None` while the device is disconnected.
+    pub info: Option<DeviceInfo>,
     pub sliders: Vec<SliderData>,
-    pub serial_out_tx: Option<UnboundedSender<CommandsOut>>,
+    pub buttons: Vec<ButtonData>,
+    pub encoders: Vec<EncoderData>,
+    /// Last state sent to each slider's LED.
+    pub leds: Vec<LedState>,
+    /// Last content sent to each slider's display.
+    pub displays: Vec<SetDisplayProps>,
+    /// Firmware update in progress or last finished, kept across reconnects
+    /// so an interrupted transfer can resume.
+    pub update: Option<Uploader>,
+    /// Why the last firmware image picked for the device was refused.
+    pub image_error: Option<String>,
+    pub link_stats: LinkStats,
+    /// Round trips of heartbeat pings, for firmware that answers them.
+    pub latency: LatencyStats,
+    pub connection_state: ConnectionState,
+}
+
+impl Device {
+    /// Capabilities announced by the device, empty until the handshake
+    /// completes.
+    pub fn capabilities(&self) -> Capabilities {
+        self.info
+            .as_ref()
+            .and_then(|device_info| device_info.hello.as_ref())
+            .map(|hello| hello.capabilities)
+            .unwrap_or_default()
+    }
+
+    /// Mutes or unmutes a slider, applies it to the audio target and tells
+    /// the device so it can update its mute LED.
+    pub fn set_muted(&mut self, index: usize, muted: bool) {
+        let Some(slider) = self.sliders.get_mut(index) else {
+            return;
+        };
+        slider.muted = muted;
+        run_action(slider);
+        self.send_command(CommandsOut::SetMute(SetMuteProps {
+            channel: index as u8 + 1,
+            muted,
+        }));
+    }
+
+    /// Moves a slider, applies it to the audio target and tells the device,
+    /// at full resolution if it takes it.
+    pub fn set_position(&mut self, index: usize, position: u16) {
+        let Some(slider) = self.sliders.get_mut(index) else {
+            return;
+        };
+        slider.position = position;
+        run_action(slider);
+        let high_res = self.capabilities().contains(Capabilities::HIGH_RES);
+        self.send_command(CommandsOut::SetVolume(SetVolumeProps::new(
+            index as u8 + 1,
+            position,
+            high_res,
+        )));
+    }
+
+    /// Moves every slider at once, one position per slider, and applies the
+    /// ones that changed to their audio targets. Firmware that takes bulk
+    /// frames gets a single one.
+    pub fn set_positions(&mut self, positions: &[u16]) {
+        if positions.len() != self.sliders.len() {
+            eprintln!(
+                "Cannot set {} positions on {} sliders",
+                positions.len(),
+                self.sliders.len()
+            );
+            return;
+        }
+
+        let mut moved = Vec::new();
+        for (index, (slider, &position)) in self.sliders.iter_mut().zip(positions).enumerate() {
+            if slider.position != position {
+                slider.position = position;
+                run_action(slider);
+                moved.push(index);
+            }
+        }
+        if moved.is_empty() {
+            return;
+        }
+
+        let capabilities = self.capabilities();
+        if capabilities.contains(Capabilities::BULK | Capabilities::HIGH_RES) {
+            self.send_command(CommandsOut::SetPositions(positions.to_vec()));
+        } else if capabilities.contains(Capabilities::BULK) {
+            self.send_command(CommandsOut::SetVolumes(
+                positions.iter().copied().map(position_percent).collect(),
+            ));
+        } else {
+            let high_res = capabilities.contains(Capabilities::HIGH_RES);
+            for index in moved {
+                self.send_command(CommandsOut::SetVolume(SetVolumeProps::new(
+                    index as u8 + 1,
+                    positions[index],
+                    high_res,
+                )));
+            }
+        }
+    }
+
+    pub fn toggle_mute(&mut self, index: usize) {
+        if let Some(slider) = self.sliders.get(index) {
+            self.set_muted(index, !slider.muted);
+        }
+    }
+
+    /// Queues a command for the device, skipping it when the device did not
+    /// announce the capability it depends on.
+    pub fn send_command(&self, command: CommandsOut) {
+        if let Some(capability) = command.required_capability()
+            && !self.capabilities().contains(capability)
+        {
+            println!("Device does not support {:?}, skipping", command);
+            return;
+        }
+
+        match &self.info {
+            Some(device_info) => {
+                let _ = device_info.serial_out_tx.unbounded_send(command);
+            }
+            None => println!("Device is disconnected, dropping {:?}", command),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Data {
+    pub devices: BTreeMap<DeviceId, Device>,
+    /// Most recent state reported by the scanner or any device.
+    pub connection_state: ConnectionState,
+    pub last_error: Option<ConnectionState>,
+    pub config: Config,
+    /// Lets pages hand slow work to a thread, the result comes back like any
+    /// device event. Set once the event loop runs.
+    pub state_tx: Option<UnboundedSender<ChannelSend>>,
+}
+
+impl Data {
+    pub fn connected_devices(&self) -> impl Iterator<Item = (&DeviceId, &Device)> {
+        self.devices
+            .iter()
+            .filter(|(_, device)| device.info.is_some())
+    }
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Copy, Hash)]
 pub enum DataChannel {
     SlidersUpdate,
     DeviceInfo,
+    ConnectionState,
+    LinkStats,
+    FirmwareUpdate,
     NoUpdate,
+    /// Written by every feedback tick, nothing renders from it.
+    Feedback,
 }
 
 impl RadioChannel<Data> for DataChannel {}
 
 pub enum ChannelSend {
-    DeviceInfoUpdate(Option<DeviceInfo>),
-    SliderVolumeUpdate(usize, u8),
-    SlidersInfoUpdate(Vec<SliderData>),
-    SliderInfoUpdate(usize, SliderData),
+    DeviceInfoUpdate(DeviceId, Option<DeviceInfo>),
+    /// New position of a 1-based channel.
+    SliderVolumeUpdate(DeviceId, usize, u16),
+    /// Positions of every channel of a device at once, starting with
+    /// channel 1.
+    SlidersVolumesUpdate(DeviceId, Vec<u16>),
+    SlidersInfoUpdate(DeviceId, Vec<SliderData>),
+    ControlsInfoUpdate(DeviceId, Vec<ButtonData>, Vec<EncoderData>),
+    ButtonInput(DeviceId, ButtonEvent),
+    EncoderInput(DeviceId, EncoderEvent),
+    /// Time to bring the device LEDs and displays in line with the app state.
+    FeedbackTick,
+    /// Progress of a firmware update, see [`Uploader`].
+    UpdateReply(DeviceId, CommandsIn),
+    /// Firmware image read for a device, see [`load_image`].
+    ImageLoaded(DeviceId, Result<FirmwareImage, String>),
+    HelloUpdate(DeviceId, HelloInfo),
+    LinkStatsUpdate(DeviceId, LinkStats),
+    LatencyUpdate(DeviceId, LatencyStats),
+    /// Scanner wide states carry no device.
+    ConnectionStateUpdate(Option<DeviceId>, ConnectionState),
+}
+
+fn tray_tooltip(data: &Data) -> String {
+    match data.connected_devices().count() {
+        0 => format!("Audiomixer - {}", data.connection_state),
+        1 => "Audiomixer - 1 device connected".to_string(),
+        connected => format!("Audiomixer - {} devices connected", connected),
+    }
 }
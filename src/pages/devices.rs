@@ -0,0 +1,144 @@
+use std::time::Duration;
+
+use freya::{prelude::*, radio::use_radio};
+use freya_router::prelude::RouterContext;
+
+use crate::{
+    DataChannel, Device,
+    app::Route,
+    utils::{Capabilities, DeviceId, LatencyStats, LinkStats},
+};
+
+/// Firmware, link health and latency of every connected mixer.
+#[derive(PartialEq)]
+pub struct Devices {}
+impl Component for Devices {
+    fn render(&self) -> impl IntoElement {
+        let radio = use_radio(DataChannel::DeviceInfo);
+        let devices = radio
+            .read()
+            .connected_devices()
+            .map(|(id, _)| DeviceDetails { id: id.clone() }.into_element())
+            .collect::<Vec<_>>();
+
+        rect().expanded().padding(8.0).spacing(8.0).children([
+            Button::new()
+                .on_press(|_| {
+                    RouterContext::get().replace(Route::Main);
+                })
+                .child("Back")
+                .into(),
+            rect()
+                .width(Size::Fill)
+                .spacing(8.0)
+                .children(devices)
+                .into(),
+        ])
+    }
+}
+
+#[derive(PartialEq)]
+struct DeviceDetails {
+    id: DeviceId,
+}
+
+impl Component for DeviceDetails {
+    fn render(&self) -> impl IntoElement {
+        // Stats change far more often than anything else shown here
+        let radio = use_radio(DataChannel::LinkStats);
+        let Some((title, lines)) = radio.read().devices.get(&self.id).map(describe) else {
+            return rect();
+        };
+
+        let mut children = vec![label().font_weight(FontWeight::BOLD).text(title).into()];
+        children.extend(lines.into_iter().map(|line| label().text(line).into()));
+
+        rect()
+            .width(Size::Fill)
+            .padding(8.0)
+            .corner_radius(8.0)
+            .background(Color::from_hex("#EEEEEE").unwrap())
+            .children(children)
+    }
+}
+
+/// Name of the device and one line per fact about it.
+fn describe(device: &Device) -> (String, Vec<String>) {
+    let Some(device_info) = &device.info else {
+        return (String::new(), Vec::new());
+    };
+
+    let mut lines = Vec::new();
+    if let Some(usb_info) = &device_info.usb_info {
+        lines.push(format!(
+            "USB {:04x}:{:04x}, serial {}",
+            usb_info.vid,
+            usb_info.pid,
+            usb_info.serial_number.as_deref().unwrap_or("-")
+        ));
+    }
+    match &device_info.hello {
+        Some(hello) => {
+            let capabilities = hello.capabilities.names();
+            lines.push(format!(
+                "Firmware {}, protocol v{}",
+                hello.firmware_version, hello.protocol_version
+            ));
+            lines.push(format!(
+                "Capabilities: {}",
+                if capabilities.is_empty() {
+                    "none".to_string()
+                } else {
+                    capabilities.join(", ")
+                }
+            ));
+        }
+        None => lines.push("Firmware predates the Hello handshake".to_string()),
+    }
+    lines.push(format!("State: {}", device.connection_state));
+    lines.extend(link_lines(&device.link_stats));
+    lines.push(latency_line(
+        &device.latency,
+        device.capabilities().contains(Capabilities::HEARTBEAT),
+    ));
+
+    (device_info.name(), lines)
+}
+
+fn link_lines(stats: &LinkStats) -> [String; 3] {
+    [
+        format!(
+            "Frames: {} sent, {} received",
+            stats.frames_sent, stats.frames_received
+        ),
+        format!(
+            "Acknowledgements: {} acks, {} nacks, {} retransmissions, {} dropped",
+            stats.acks, stats.nacks, stats.retransmissions, stats.dropped
+        ),
+        format!(
+            "Errors: {} checksum, {} framing, {} decoding",
+            stats.crc_errors, stats.framing_errors, stats.decode_errors
+        ),
+    ]
+}
+
+fn latency_line(latency: &LatencyStats, heartbeat: bool) -> String {
+    if !heartbeat {
+        return "Latency: not measured, the firmware does not answer pings".to_string();
+    }
+    match (latency.last, latency.average(), latency.min, latency.max) {
+        (Some(last), Some(average), Some(min), Some(max)) => format!(
+            "Latency: {} (average {}, min {}, max {}), {} pings missed",
+            millis(last),
+            millis(average),
+            millis(min),
+            millis(max),
+            latency.missed
+        ),
+        _ => format!("Latency: no pong yet, {} pings missed", latency.missed),
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1} ms", duration.as_secs_f64() * 1000.0)
+}
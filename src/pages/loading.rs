@@ -1,24 +1,45 @@
 use freya::{prelude::*, radio::use_radio};
 use freya_router::prelude::RouterContext;
 
-use crate::{Data, DataChannel, app::Route};
+use crate::{Data, DataChannel, app::Route, components::PermissionHelp, utils::ConnectionState};
 
 #[derive(PartialEq)]
 pub struct Loading {}
 impl Component for Loading {
     fn render(&self) -> impl IntoElement {
         let radio = use_radio::<Data, DataChannel>(DataChannel::DeviceInfo);
+        let connection_radio = use_radio::<Data, DataChannel>(DataChannel::ConnectionState);
+        let state = connection_radio.read().connection_state.clone();
+        let last_error = connection_radio.read().last_error.clone();
 
         use_side_effect(move || {
-            if radio.read().device_info.is_none() {
+            if radio.read().connected_devices().next().is_none() {
                 return;
             }
             RouterContext::get().replace(Route::Main);
         });
 
-        rect()
-            .expanded()
-            .center()
-            .child(label().text("Looking for a device..."))
+        let mut children = vec![label().text(state.to_string()).into()];
+        if let Some(ConnectionState::PermissionDenied(port)) = last_error {
+            children.push(PermissionHelp::new(port).into_element());
+        } else if let Some(last_error) = last_error {
+            children.push(
+                label()
+                    .color(Color::from_hex("#B00020").unwrap())
+                    .text(format!("Last error: {}", last_error))
+                    .into(),
+            );
+        }
+
+        children.push(
+            Button::new()
+                .on_press(|_| {
+                    RouterContext::get().replace(Route::Ports);
+                })
+                .child("Show serial ports")
+                .into(),
+        );
+
+        rect().expanded().center().spacing(8.0).children(children)
     }
 }
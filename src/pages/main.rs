@@ -1,83 +1,265 @@
 use freya::{prelude::*, radio::use_radio};
+use freya_router::prelude::RouterContext;
 
 use crate::{
     DataChannel,
+    app::Route,
     components::Slider,
-    utils::{CommandsOut, SetVolumeProps, run_action},
+    utils::{ButtonAction, DeviceId, EncoderAction, MAX_POSITION, next_option},
 };
 
 #[derive(PartialEq)]
 pub struct Main {}
 impl Component for Main {
     fn render(&self) -> impl IntoElement {
-        let mut radio = use_radio(DataChannel::SlidersUpdate);
-        let tx_option = radio.read().serial_out_tx.clone();
+        let device_radio = use_radio(DataChannel::DeviceInfo);
+
+        use_side_effect(move || {
+            if device_radio.read().connected_devices().next().is_some() {
+                return;
+            }
+            RouterContext::get().replace(Route::Loading);
+        });
 
         rect()
             .width(Size::percent(100.0))
             .height(Size::percent(100.0))
-            .children([
-                rect()
-                    .width(Size::Fill)
-                    .height(Size::px(60.0))
-                    .background(Color::from_hex("#FFFFFF").unwrap())
-                    .child(
-                        rect()
-                            .height(Size::Fill)
-                            .main_align(Alignment::Center)
-                            .children([
-                                label()
-                                    .font_size(16.0)
-                                    .font_weight(FontWeight::BOLD)
-                                    .text("Device")
-                                    .into(),
-                                label()
-                                    .font_size(16.0)
-                                    .font_weight(FontWeight::BOLD)
-                                    .text(" - Audiomixer")
-                                    .into(),
-                            ]),
-                    )
-                    .into(),
-                rect()
-                    .width(Size::Fill)
-                    .height(Size::Fill)
-                    .content(Content::Flex)
-                    .direction(Direction::Horizontal)
-                    .padding(8.0)
-                    .spacing(8.0)
-                    .children(
-                        radio
-                            .read()
-                            .sliders
-                            .iter()
-                            .enumerate()
-                            .map(|(index, slider)| {
-                                Slider::new()
-                                    .title(slider.name.clone())
-                                    .width(Size::flex(1.0))
-                                    .value(slider.volume as f64)
-                                    .on_change({
-                                        let slider_clone = slider.clone();
-                                        let tx = tx_option.clone();
-                                        move |val: f64| {
-                                            radio.write().sliders[index].volume = val as u8;
-                                            tx.clone()
-                                                .expect("Sender for commands is not set")
-                                                .unbounded_send(CommandsOut::SetVolume(
-                                                    SetVolumeProps {
-                                                        channel: index as u8 + 1,
-                                                        volume: val as u8,
-                                                    },
-                                                ))
-                                                .expect("Failed to send to channel");
-                                            run_action(&radio.read().sliders[index]);
-                                        }
-                                    })
-                                    .into_element()
-                            }),
-                    )
-                    .into(),
-            ])
+            .content(Content::Flex)
+            .children(
+                device_radio
+                    .read()
+                    .connected_devices()
+                    .map(|(id, device)| {
+                        let name = device
+                            .info
+                            .as_ref()
+                            .map(|device_info| device_info.name())
+                            .unwrap_or_default();
+                        let firmware = device
+                            .info
+                            .as_ref()
+                            .and_then(|device_info| device_info.hello.as_ref())
+                            .map(|hello| {
+                                let capabilities = hello.capabilities.names();
+                                if capabilities.is_empty() {
+                                    format!(" (firmware {})", hello.firmware_version)
+                                } else {
+                                    format!(
+                                        " (firmware {}, {})",
+                                        hello.firmware_version,
+                                        capabilities.join(", ")
+                                    )
+                                }
+                            })
+                            .unwrap_or_default();
+
+                        DeviceSection {
+                            id: id.clone(),
+                            title: format!("{} - Audiomixer{}", name, firmware),
+                        }
+                        .into_element()
+                    })
+                    .collect::<Vec<_>>(),
+            )
+    }
+}
+
+/// Header, slider bank and controls of a single mixer.
+#[derive(PartialEq)]
+struct DeviceSection {
+    id: DeviceId,
+    title: String,
+}
+
+impl Component for DeviceSection {
+    fn render(&self) -> impl IntoElement {
+        let mut radio = use_radio(DataChannel::SlidersUpdate);
+        let sliders = radio
+            .read()
+            .devices
+            .get(&self.id)
+            .map(|device| device.sliders.clone())
+            .unwrap_or_default();
+
+        rect().width(Size::Fill).height(Size::flex(1.0)).children([
+            rect()
+                .width(Size::Fill)
+                .height(Size::px(60.0))
+                .background(Color::from_hex("#FFFFFF").unwrap())
+                .direction(Direction::Horizontal)
+                .spacing(8.0)
+                .children([
+                    rect()
+                        .height(Size::Fill)
+                        .main_align(Alignment::Center)
+                        .child(
+                            label()
+                                .font_size(16.0)
+                                .font_weight(FontWeight::BOLD)
+                                .text(self.title.clone()),
+                        )
+                        .into(),
+                    rect()
+                        .height(Size::Fill)
+                        .main_align(Alignment::Center)
+                        .child(
+                            Button::new()
+                                .on_press(|_| {
+                                    RouterContext::get().replace(Route::Settings);
+                                })
+                                .child("Settings"),
+                        )
+                        .into(),
+                    rect()
+                        .height(Size::Fill)
+                        .main_align(Alignment::Center)
+                        .child(
+                            Button::new()
+                                .on_press(|_| {
+                                    RouterContext::get().replace(Route::Devices);
+                                })
+                                .child("Device info"),
+                        )
+                        .into(),
+                ])
+                .into(),
+            rect()
+                .width(Size::Fill)
+                .height(Size::Fill)
+                .content(Content::Flex)
+                .direction(Direction::Horizontal)
+                .padding(8.0)
+                .spacing(8.0)
+                .children(sliders.into_iter().enumerate().map(|(index, slider)| {
+                    let id = self.id.clone();
+                    let mute_id = self.id.clone();
+                    Slider::new()
+                        .title(slider.name)
+                        .width(Size::flex(1.0))
+                        .value(slider.level() * 100.0)
+                        .muted(slider.muted)
+                        .on_toggle_mute(move |_| {
+                            if let Some(device) = radio.write().devices.get_mut(&mute_id) {
+                                device.toggle_mute(index);
+                            }
+                        })
+                        .on_change(move |val: f64| {
+                            if let Some(device) = radio.write().devices.get_mut(&id) {
+                                let position = val / 100.0 * MAX_POSITION as f64;
+                                device.set_position(index, position.round() as u16);
+                            }
+                        })
+                        .into_element()
+                }))
+                .into(),
+            Controls {
+                id: self.id.clone(),
+            }
+            .into_element(),
+        ])
+    }
+}
+
+/// Buttons and encoders of a mixer, clicking a binding moves to the next one.
+#[derive(PartialEq)]
+struct Controls {
+    id: DeviceId,
+}
+
+impl Component for Controls {
+    fn render(&self) -> impl IntoElement {
+        let mut radio = use_radio(DataChannel::SlidersUpdate);
+        let profiles = radio.read().config.profiles.len();
+        let (sliders, buttons, encoders) = radio
+            .read()
+            .devices
+            .get(&self.id)
+            .map(|device| {
+                (
+                    device.sliders.len(),
+                    device.buttons.clone(),
+                    device.encoders.clone(),
+                )
+            })
+            .unwrap_or_default();
+
+        let buttons = buttons.into_iter().enumerate().map(|(index, button)| {
+            let press_id = self.id.clone();
+            let long_press_id = self.id.clone();
+            rect()
+                .spacing(4.0)
+                .children([
+                    label()
+                        .font_weight(FontWeight::BOLD)
+                        .text(button.name)
+                        .into(),
+                    Button::new()
+                        .on_press(move |_| {
+                            let mut data = radio.write();
+                            if let Some(button) = data
+                                .devices
+                                .get_mut(&press_id)
+                                .and_then(|device| device.buttons.get_mut(index))
+                            {
+                                button.press = next_option(
+                                    &ButtonAction::options(sliders, profiles),
+                                    &button.press,
+                                );
+                            }
+                        })
+                        .child(label().text(format!("Press: {}", button.press)))
+                        .into(),
+                    Button::new()
+                        .on_press(move |_| {
+                            let mut data = radio.write();
+                            if let Some(button) = data
+                                .devices
+                                .get_mut(&long_press_id)
+                                .and_then(|device| device.buttons.get_mut(index))
+                            {
+                                button.long_press = next_option(
+                                    &ButtonAction::options(sliders, profiles),
+                                    &button.long_press,
+                                );
+                            }
+                        })
+                        .child(label().text(format!("Hold: {}", button.long_press)))
+                        .into(),
+                ])
+                .into_element()
+        });
+        let encoders = encoders.into_iter().enumerate().map(|(index, encoder)| {
+            let id = self.id.clone();
+            rect()
+                .spacing(4.0)
+                .children([
+                    label()
+                        .font_weight(FontWeight::BOLD)
+                        .text(encoder.name)
+                        .into(),
+                    Button::new()
+                        .on_press(move |_| {
+                            let mut data = radio.write();
+                            if let Some(encoder) = data
+                                .devices
+                                .get_mut(&id)
+                                .and_then(|device| device.encoders.get_mut(index))
+                            {
+                                encoder.action =
+                                    next_option(&EncoderAction::options(sliders), &encoder.action);
+                            }
+                        })
+                        .child(label().text(format!("Turn: {}", encoder.action)))
+                        .into(),
+                ])
+                .into_element()
+        });
+
+        rect()
+            .width(Size::Fill)
+            .direction(Direction::Horizontal)
+            .padding(8.0)
+            .spacing(16.0)
+            .children(buttons.chain(encoders).collect::<Vec<_>>())
     }
 }
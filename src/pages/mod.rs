@@ -1,4 +1,12 @@
+mod devices;
+pub use devices::*;
 mod loading;
 pub use loading::*;
 mod main;
 pub use main::*;
+mod ports;
+pub use ports::*;
+mod settings;
+pub use settings::*;
+mod update;
+pub use update::*;
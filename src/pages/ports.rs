@@ -0,0 +1,93 @@
+use freya::{prelude::*, radio::use_radio};
+use freya_router::prelude::RouterContext;
+
+use crate::{
+    Data, DataChannel,
+    app::Route,
+    utils::{Config, PortCandidate, list_candidate_ports},
+};
+
+/// Every serial port the OS reports and why it was or wasn't picked.
+#[derive(PartialEq)]
+pub struct Ports {}
+impl Component for Ports {
+    fn render(&self) -> impl IntoElement {
+        let radio = use_radio::<Data, DataChannel>(DataChannel::NoUpdate);
+        // Enumerating ports is slow, only done on open and on Refresh
+        let mut candidates = use_state(|| list_ports(&radio.read().config));
+
+        let rows = match candidates.read().clone() {
+            Ok(candidates) if candidates.is_empty() => {
+                vec![label().text("No serial ports found").into()]
+            }
+            Ok(candidates) => candidates
+                .into_iter()
+                .map(|candidate| {
+                    let usb = candidate
+                        .usb_info
+                        .map(|info| {
+                            format!(
+                                "{:04x}:{:04x} {} {} serial {}",
+                                info.vid,
+                                info.pid,
+                                info.manufacturer.unwrap_or_default(),
+                                info.product.unwrap_or_default(),
+                                info.serial_number.unwrap_or_else(|| "-".to_string())
+                            )
+                        })
+                        .unwrap_or_else(|| "not USB".to_string());
+                    let color = if candidate.selected {
+                        "#1B5E20"
+                    } else {
+                        "#616161"
+                    };
+
+                    rect()
+                        .width(Size::Fill)
+                        .padding(8.0)
+                        .corner_radius(8.0)
+                        .background(Color::from_hex("#EEEEEE").unwrap())
+                        .children([
+                            label()
+                                .font_weight(FontWeight::BOLD)
+                                .text(candidate.port_name)
+                                .into(),
+                            label().text(usb).into(),
+                            label()
+                                .color(Color::from_hex(color).unwrap())
+                                .text(candidate.reason)
+                                .into(),
+                        ])
+                        .into()
+                })
+                .collect(),
+            Err(e) => vec![label().text(format!("Failed to list ports: {}", e)).into()],
+        };
+
+        rect().expanded().padding(8.0).spacing(8.0).children([
+            rect()
+                .direction(Direction::Horizontal)
+                .spacing(8.0)
+                .children([
+                    Button::new()
+                        .on_press(|_| {
+                            RouterContext::get().replace(Route::Loading);
+                        })
+                        .child("Back")
+                        .into(),
+                    Button::new()
+                        .on_press(move |_| {
+                            candidates.set(list_ports(&radio.read().config));
+                        })
+                        .child("Refresh")
+                        .into(),
+                ])
+                .into(),
+            rect().width(Size::Fill).spacing(8.0).children(rows).into(),
+        ])
+    }
+}
+
+fn list_ports(config: &Config) -> Result<Vec<PortCandidate>, String> {
+    list_candidate_ports(config).map_err(|e| e.to_string())
+}
@@ -0,0 +1,278 @@
+use freya::{prelude::*, radio::use_radio};
+use freya_router::prelude::RouterContext;
+
+use crate::{
+    DataChannel, Device,
+    app::Route,
+    utils::{
+        Capabilities, CommandsOut, DeviceId, MAX_LABEL_LEN, RenameSliderProps,
+        SetSliderActionProps, VolumeAction, run_action,
+    },
+};
+
+/// Slider names and actions stored on every connected mixer.
+#[derive(PartialEq)]
+pub struct Settings {}
+impl Component for Settings {
+    fn render(&self) -> impl IntoElement {
+        let radio = use_radio(DataChannel::DeviceInfo);
+        let devices = radio
+            .read()
+            .connected_devices()
+            .map(|(id, device)| {
+                DeviceSettings {
+                    id: id.clone(),
+                    title: device
+                        .info
+                        .as_ref()
+                        .map(|device_info| device_info.name())
+                        .unwrap_or_default(),
+                }
+                .into_element()
+            })
+            .collect::<Vec<_>>();
+
+        rect().expanded().padding(8.0).spacing(8.0).children([
+            rect()
+                .direction(Direction::Horizontal)
+                .spacing(8.0)
+                .children([
+                    Button::new()
+                        .on_press(|_| {
+                            RouterContext::get().replace(Route::Main);
+                        })
+                        .child("Back")
+                        .into(),
+                    Button::new()
+                        .on_press(|_| {
+                            RouterContext::get().replace(Route::Update);
+                        })
+                        .child("Firmware update")
+                        .into(),
+                ])
+                .into(),
+            rect()
+                .width(Size::Fill)
+                .spacing(16.0)
+                .children(devices)
+                .into(),
+        ])
+    }
+}
+
+/// Longest slider name, in bytes. The firmware keeps names in fixed size
+/// flash slots and shows them on the channel displays.
+const MAX_NAME_LEN: usize = MAX_LABEL_LEN;
+
+/// Names and actions being edited, along with the sliders they started
+/// from.
+#[derive(Clone)]
+struct Drafts {
+    seed: Vec<(String, String)>,
+    edits: Vec<(String, String)>,
+}
+
+impl Drafts {
+    fn new(sliders: Vec<(String, String)>) -> Self {
+        Self {
+            edits: sliders.clone(),
+            seed: sliders,
+        }
+    }
+
+    /// The edits, or `sliders` untouched when the edits were started on
+    /// other sliders, e.g. before the device reconnected.
+    fn of(&self, sliders: &[(String, String)]) -> Vec<(String, String)> {
+        if self.seed == sliders {
+            self.edits.clone()
+        } else {
+            sliders.to_vec()
+        }
+    }
+
+    fn edit(
+        &mut self,
+        sliders: &[(String, String)],
+        index: usize,
+        edit: impl FnOnce(&mut (String, String)),
+    ) {
+        if self.seed != sliders {
+            *self = Self::new(sliders.to_vec());
+        }
+        if let Some(slider) = self.edits.get_mut(index) {
+            edit(slider);
+        }
+    }
+}
+
+#[derive(PartialEq)]
+struct DeviceSettings {
+    id: DeviceId,
+    title: String,
+}
+
+impl Component for DeviceSettings {
+    fn render(&self) -> impl IntoElement {
+        let mut radio = use_radio(DataChannel::SlidersUpdate);
+        let (sliders, configurable) = radio
+            .read()
+            .devices
+            .get(&self.id)
+            .map(|device| {
+                (
+                    slider_config(device),
+                    device.capabilities().contains(Capabilities::CONFIG),
+                )
+            })
+            .unwrap_or_default();
+        // Edited names and actions, only sent when saving. Dropped when the
+        // device reports other sliders, so they never land on the wrong ones.
+        let mut drafts = use_state(|| Drafts::new(sliders.clone()));
+        let mut status = use_state(|| None::<String>);
+
+        let mut children = vec![
+            label()
+                .font_size(16.0)
+                .font_weight(FontWeight::BOLD)
+                .text(self.title.clone())
+                .into(),
+        ];
+        if !configurable {
+            children.push(
+                label()
+                    .text("This firmware cannot change its slider configuration.")
+                    .into(),
+            );
+            return rect().width(Size::Fill).spacing(8.0).children(children);
+        }
+
+        children.extend(drafts.read().of(&sliders).into_iter().enumerate().map(
+            |(index, (name, action))| {
+                let name_sliders = sliders.clone();
+                let action_sliders = sliders.clone();
+                rect()
+                    .direction(Direction::Horizontal)
+                    .cross_align(Alignment::Center)
+                    .spacing(8.0)
+                    .children([
+                        label().text(format!("Slider {}", index + 1)).into(),
+                        Input::new()
+                            .value(name)
+                            .on_change(move |name: String| {
+                                drafts
+                                    .write()
+                                    .edit(&name_sliders, index, |draft| draft.0 = name);
+                            })
+                            .into(),
+                        Input::new()
+                            .value(action)
+                            .on_change(move |action: String| {
+                                drafts
+                                    .write()
+                                    .edit(&action_sliders, index, |draft| draft.1 = action);
+                            })
+                            .into(),
+                    ])
+                    .into()
+            },
+        ));
+
+        let id = self.id.clone();
+        children.push(
+            Button::new()
+                .on_press(move |_| {
+                    let mut data = radio.write();
+                    let allow_commands = data.config.allow_commands;
+                    if let Some(device) = data.devices.get_mut(&id) {
+                        let drafts = drafts.read().of(&slider_config(device));
+                        status.set(Some(save_config(device, &drafts, allow_commands)));
+                    }
+                })
+                .child("Save to device")
+                .into(),
+        );
+        if let Some(status) = status.read().clone() {
+            children.push(label().text(status).into());
+        }
+
+        rect().width(Size::Fill).spacing(8.0).children(children)
+    }
+}
+
+/// Name and device action of every slider, what the page edits.
+fn slider_config(device: &Device) -> Vec<(String, String)> {
+    device
+        .sliders
+        .iter()
+        .map(|slider| (slider.name.clone(), slider.device_action.clone()))
+        .collect()
+}
+
+/// Sends the names and actions that differ from what the device reported,
+/// then has it write them to flash. Returns a line for the user.
+///
+/// `cmd:` targets are only bound with `allow_commands`, see
+/// [`VolumeAction::from_user`].
+fn save_config(device: &mut Device, drafts: &[(String, String)], allow_commands: bool) -> String {
+    if drafts.iter().any(|(name, _)| name.trim().is_empty()) {
+        return "Slider names cannot be empty".to_string();
+    }
+    if let Some(index) = drafts
+        .iter()
+        .position(|(name, _)| name.len() > MAX_NAME_LEN)
+    {
+        return format!(
+            "Slider {}: names can be at most {} bytes long",
+            index + 1,
+            MAX_NAME_LEN
+        );
+    }
+    if drafts.len() != device.sliders.len() {
+        return "The sliders changed on the device, check them and save again".to_string();
+    }
+    let mut targets = Vec::new();
+    for (index, (_, action)) in drafts.iter().enumerate() {
+        match VolumeAction::from_user(action, allow_commands) {
+            Ok(target) => targets.push(target),
+            Err(e) => return format!("Slider {}: {}", index + 1, e),
+        }
+    }
+
+    let mut commands = Vec::new();
+    for (index, ((slider, (name, action)), target)) in device
+        .sliders
+        .iter_mut()
+        .zip(drafts)
+        .zip(targets)
+        .enumerate()
+    {
+        let channel = index as u8 + 1;
+        if slider.name != *name {
+            slider.name = name.clone();
+            commands.push(CommandsOut::RenameSlider(RenameSliderProps {
+                channel,
+                name: name.clone(),
+            }));
+        }
+        if slider.device_action != *action {
+            slider.device_action = action.clone();
+            slider.set_volume_action = target;
+            // The new target starts at the fader position
+            run_action(slider);
+            commands.push(CommandsOut::SetSliderAction(SetSliderActionProps {
+                channel,
+                action: action.clone(),
+            }));
+        }
+    }
+
+    if commands.is_empty() {
+        return "Nothing to save".to_string();
+    }
+    let changes = commands.len();
+    for command in commands {
+        device.send_command(command);
+    }
+    device.send_command(CommandsOut::SaveConfig);
+    format!("Saved {} change(s) to the device", changes)
+}
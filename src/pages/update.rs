@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use freya::{prelude::*, radio::use_radio};
+use freya_router::prelude::RouterContext;
+
+use crate::{
+    DataChannel,
+    app::Route,
+    utils::{DeviceId, UploadState, load_image},
+};
+
+/// Firmware of every connected mixer and the progress of its update.
+#[derive(PartialEq)]
+pub struct Update {}
+impl Component for Update {
+    fn render(&self) -> impl IntoElement {
+        let radio = use_radio(DataChannel::DeviceInfo);
+        let devices = radio
+            .read()
+            .connected_devices()
+            .map(|(id, device)| {
+                DeviceUpdate {
+                    id: id.clone(),
+                    title: device
+                        .info
+                        .as_ref()
+                        .map(|device_info| device_info.name())
+                        .unwrap_or_default(),
+                }
+                .into_element()
+            })
+            .collect::<Vec<_>>();
+
+        rect().expanded().padding(8.0).spacing(8.0).children([
+            Button::new()
+                .on_press(|_| {
+                    RouterContext::get().replace(Route::Settings);
+                })
+                .child("Back")
+                .into(),
+            rect()
+                .width(Size::Fill)
+                .spacing(16.0)
+                .children(devices)
+                .into(),
+        ])
+    }
+}
+
+#[derive(PartialEq)]
+struct DeviceUpdate {
+    id: DeviceId,
+    title: String,
+}
+
+impl Component for DeviceUpdate {
+    fn render(&self) -> impl IntoElement {
+        let mut radio = use_radio(DataChannel::FirmwareUpdate);
+        let mut path = use_state(String::new);
+        let (firmware, update, error) = radio
+            .read()
+            .devices
+            .get(&self.id)
+            .map(|device| {
+                let firmware = device
+                    .info
+                    .as_ref()
+                    .and_then(|device_info| device_info.hello.as_ref())
+                    .map(|hello| hello.firmware_version.to_string())
+                    .unwrap_or_else(|| "unknown".to_string());
+                let update = device.update.as_ref().map(|uploader| {
+                    (
+                        uploader.image().version,
+                        uploader.state().clone(),
+                        uploader.progress(),
+                    )
+                });
+                (firmware, update, device.image_error.clone())
+            })
+            .unwrap_or_default();
+        let updating = update
+            .as_ref()
+            .is_some_and(|(_, state, _)| state.is_active());
+
+        let mut children = vec![
+            label()
+                .font_size(16.0)
+                .font_weight(FontWeight::BOLD)
+                .text(self.title.clone())
+                .into(),
+            label().text(format!("Firmware {}", firmware)).into(),
+        ];
+
+        if !updating {
+            let id = self.id.clone();
+            children.push(
+                rect()
+                    .direction(Direction::Horizontal)
+                    .cross_align(Alignment::Center)
+                    .spacing(8.0)
+                    .children([
+                        label().text("Image (.bin)").into(),
+                        Input::new()
+                            .value(path.read().clone())
+                            .on_change(move |value: String| path.set(value))
+                            .into(),
+                        Button::new()
+                            .on_press(move |_| {
+                                let mut data = radio.write();
+                                let Some(state_tx) = data.state_tx.clone() else {
+                                    return;
+                                };
+                                let Some(device) = data.devices.get_mut(&id) else {
+                                    return;
+                                };
+                                device.image_error = None;
+                                load_image(id.clone(), PathBuf::from(path.read().trim()), state_tx);
+                            })
+                            .child("Update")
+                            .into(),
+                    ])
+                    .into(),
+            );
+        }
+        if let Some(error) = error {
+            children.push(
+                label()
+                    .color(Color::from_hex("#B00020").unwrap())
+                    .text(error)
+                    .into(),
+            );
+        }
+
+        if let Some((version, state, progress)) = update {
+            let status = match state {
+                UploadState::Starting => format!("Starting the update to {}", version),
+                UploadState::Sending => {
+                    format!("Sending {}: {:.0}%", version, progress * 100.0)
+                }
+                UploadState::Finishing => format!("Checking {} on the device", version),
+                UploadState::Done => format!("Updated to {}, the device is restarting", version),
+                UploadState::Failed(e) => format!("Update to {} failed: {}", version, e),
+            };
+            children.push(label().text(status).into());
+            children.push(
+                rect()
+                    .width(Size::Fill)
+                    .height(Size::px(12.0))
+                    .corner_radius(6.0)
+                    .background(Color::from_hex("#E0E0E0").unwrap())
+                    .child(
+                        rect()
+                            .width(Size::percent(progress as f32 * 100.0))
+                            .height(Size::Fill)
+                            .corner_radius(6.0)
+                            .background(Color::from_hex("#1B5E20").unwrap()),
+                    )
+                    .into(),
+            );
+        }
+
+        rect().width(Size::Fill).spacing(8.0).children(children)
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{READ_TIMEOUT, Transport};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From the device to the app.
+    In,
+    Out,
+}
+
+/// One line of a capture file.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CaptureEntry {
+    /// Microseconds since the capture started, which is before the session
+    /// of `device` opened its port.
+    t_us: u64,
+    device: String,
+    dir: Direction,
+    /// Raw bytes as on the wire, hex encoded.
+    data: String,
+}
+
+/// Writes everything that crosses the wire to a file, one JSON object per
+/// line, so a bug report can come with the exact traffic that caused it.
+pub struct Capture {
+    file: Mutex<File>,
+    started: Instant,
+}
+
+impl Capture {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            file: Mutex::new(File::create(path)?),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn record(&self, device: &str, dir: Direction, data: &[u8]) {
+        let entry = CaptureEntry {
+            t_us: self.started.elapsed().as_micros() as u64,
+            device: device.to_string(),
+            dir,
+            data: data.iter().map(|byte| format!("{byte:02x}")).collect(),
+        };
+        let Ok(line) = serde_json::to_string(&entry) else {
+            return;
+        };
+        // A line per write, whatever was captured survives a crash
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            eprintln!("Failed to write capture: {}", e);
+        }
+    }
+}
+
+/// Passes everything through to `inner` and records it in a [`Capture`].
+pub struct Recording {
+    inner: Box<dyn Transport>,
+    capture: Arc<Capture>,
+    device: String,
+}
+
+impl Recording {
+    pub fn new(inner: Box<dyn Transport>, capture: Arc<Capture>, device: String) -> Self {
+        Self {
+            inner,
+            capture,
+            device,
+        }
+    }
+}
+
+impl Read for Recording {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.capture.record(&self.device, Direction::In, &buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Recording {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.capture.record(&self.device, Direction::Out, &buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Transport for Recording {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(Recording {
+            inner: self.inner.try_clone_transport()?,
+            capture: self.capture.clone(),
+            device: self.device.clone(),
+        }))
+    }
+}
+
+struct ReplayState {
+    /// Bytes the device sent and when, relative to its first captured
+    /// traffic.
+    pending: VecDeque<(Duration, Vec<u8>)>,
+    started: Instant,
+    finished: bool,
+}
+
+/// Plays back what a device sent in a [`Capture`], with its original timing,
+/// as if that device was attached. Whatever the app writes is dropped.
+///
+/// Timestamps are rebased on the first traffic of the device, usually the
+/// app's Hello, so the time the original scan took before opening the port
+/// does not delay the replies.
+///
+/// Only the first device in the capture is replayed. Once the capture runs
+/// out the device stays silent instead of disconnecting, so the app keeps
+/// showing the final state.
+#[derive(Clone)]
+pub struct Replay {
+    state: Arc<Mutex<ReplayState>>,
+}
+
+impl Replay {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let source = std::fs::read_to_string(path)?;
+        let mut device = None;
+        let mut origin = None;
+        let mut pending = VecDeque::new();
+
+        for (index, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: CaptureEntry = serde_json::from_str(line).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: {}", index + 1, e),
+                )
+            })?;
+
+            let device = device.get_or_insert_with(|| entry.device.clone());
+            if entry.device != *device {
+                continue;
+            }
+            let origin = *origin.get_or_insert(entry.t_us);
+            if entry.dir != Direction::In {
+                continue;
+            }
+            let at = Duration::from_micros(entry.t_us.saturating_sub(origin));
+            pending.push_back((at, decode_hex(&entry.data)?));
+        }
+
+        println!(
+            "Replaying {} reads of {} from {}",
+            pending.len(),
+            device.unwrap_or_default(),
+            path.display()
+        );
+        Ok(Self {
+            state: Arc::new(Mutex::new(ReplayState {
+                pending,
+                started: Instant::now(),
+                finished: false,
+            })),
+        })
+    }
+}
+
+impl Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let due = match state.pending.front() {
+            Some((at, _)) => state.started + *at,
+            None => {
+                if !std::mem::replace(&mut state.finished, true) {
+                    println!("Replay finished");
+                }
+                drop(state);
+                thread::sleep(READ_TIMEOUT);
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+        };
+
+        if due > now {
+            drop(state);
+            thread::sleep((due - now).min(READ_TIMEOUT));
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        let (_, data) = state.pending.front_mut().unwrap();
+        let n = buf.len().min(data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        data.drain(..n);
+        if data.is_empty() {
+            state.pending.pop_front();
+        }
+        Ok(n)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Replay {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+fn decode_hex(hex: &str) -> io::Result<Vec<u8>> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid hex {hex}"));
+    if !hex.len().is_multiple_of(2) {
+        return Err(invalid());
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| {
+            hex.get(index..index + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or_else(invalid)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(t_us: u64, device: &str, dir: &str, data: &str) -> String {
+        format!(r#"{{"t_us":{t_us},"device":"{device}","dir":"{dir}","data":"{data}"}}"#)
+    }
+
+    fn replay(name: &str, lines: &[String]) -> Replay {
+        let path =
+            std::env::temp_dir().join(format!("audiomixer-{}-{}.jsonl", name, std::process::id()));
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let replay = Replay::open(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        replay
+    }
+
+    fn read(replay: &mut Replay) -> io::Result<Vec<u8>> {
+        let mut buf = [0; 64];
+        replay.read(&mut buf).map(|n| buf[..n].to_vec())
+    }
+
+    #[test]
+    fn timing_starts_at_the_first_traffic_of_the_device() {
+        // The session opened 30 s into the capture, the reply took 1 ms
+        let mut replay = replay(
+            "rebase",
+            &[
+                line(30_000_000, "dev", "out", "0103"),
+                line(30_001_000, "dev", "in", "aabb"),
+            ],
+        );
+
+        let started = Instant::now();
+        let data = loop {
+            match read(&mut replay) {
+                Ok(data) => break data,
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+            }
+            assert!(started.elapsed() < Duration::from_secs(1));
+        };
+        assert_eq!(data, [0xAA, 0xBB]);
+    }
+
+    #[test]
+    fn only_the_first_device_is_replayed() {
+        let mut replay = replay(
+            "devices",
+            &[
+                line(0, "first", "in", "01"),
+                line(0, "second", "in", "02"),
+                line(0, "first", "in", "03"),
+            ],
+        );
+        assert_eq!(read(&mut replay).unwrap(), [0x01]);
+        assert_eq!(read(&mut replay).unwrap(), [0x03]);
+        assert_eq!(
+            read(&mut replay).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+    }
+
+    #[test]
+    fn bad_hex_is_rejected() {
+        assert!(decode_hex("abc").is_err());
+        assert!(decode_hex("zz").is_err());
+        assert_eq!(decode_hex("00ff").unwrap(), [0x00, 0xFF]);
+    }
+}
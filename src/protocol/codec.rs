@@ -0,0 +1,1222 @@
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::MAX_FRAME_LEN;
+
+/// Protocol revision spoken by this app, sent with the Hello handshake.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// Full scale of a fader position. Firmware with [`Capabilities::HIGH_RES`]
+/// scales its 12 or 16-bit readings to this range.
+pub const MAX_POSITION: u16 = u16::MAX;
+
+/// Whole percent closest to a fader position.
+pub fn position_percent(position: u16) -> u8 {
+    ((position as u32 * 100 + MAX_POSITION as u32 / 2) / MAX_POSITION as u32) as u8
+}
+
+/// Fader position of a whole percent, anything above 100 is full scale.
+pub fn percent_position(percent: u8) -> u16 {
+    (percent.min(100) as u32 * MAX_POSITION as u32 / 100) as u16
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetVolumeProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    /// Whole percent, all that firmware without [`Capabilities::HIGH_RES`]
+    /// reads.
+    pub volume: u8,
+    pub fine_position: Option<u16>,
+}
+
+impl SetVolumeProps {
+    /// Moves a channel to `position`, only sent at full resolution to
+    /// firmware that takes it.
+    pub fn new(channel: u8, position: u16, high_res: bool) -> Self {
+        Self {
+            channel,
+            volume: position_percent(position),
+            fine_position: high_res.then_some(position),
+        }
+    }
+
+    pub fn position(&self) -> u16 {
+        self.fine_position
+            .unwrap_or_else(|| percent_position(self.volume))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetMuteProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    pub muted: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetLedProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    pub color: Rgb,
+    /// 0 turns the LED off.
+    pub brightness: u8,
+}
+
+/// Side of the square monochrome icons shown on channel displays.
+pub const ICON_SIZE: usize = 16;
+/// Longest label a channel display takes, in bytes.
+pub const MAX_LABEL_LEN: usize = 16;
+
+/// A monochrome bitmap, one row per entry from the top, most significant bit
+/// leftmost.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Icon(pub [u16; ICON_SIZE]);
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetDisplayProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    /// Cut to [`MAX_LABEL_LEN`] bytes when encoded.
+    pub label: String,
+    pub percentage: u8,
+    pub icon: Option<Icon>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenameSliderProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetSliderActionProps {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    /// New `set_volume_action` string reported in [`DeviceSliderData`].
+    pub action: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StartUpdateProps {
+    /// Length of the whole image in bytes.
+    pub size: u32,
+    /// CRC-32 of the whole image, checked before the device boots it.
+    pub checksum: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateChunkProps {
+    /// Position of `data` in the image.
+    pub offset: u32,
+    /// CRC-16 of `data`, the device asks for the chunk again on a mismatch.
+    pub crc: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandsOut {
+    Hello,
+    RequestInfo,
+    SetVolume(SetVolumeProps),
+    /// Asks the device to report the position of a 1-based channel.
+    RequestVolume(u8),
+    /// Asks the device to report every channel at once with a
+    /// [`CommandsIn::SendVolumes`].
+    RequestVolumes,
+    /// Sets every channel at once, starting with channel 1.
+    SetVolumes(Vec<u8>),
+    /// [`CommandsOut::SetVolumes`] at full resolution.
+    SetPositions(Vec<u16>),
+    /// Tells the device a channel was muted or unmuted, so it can light its
+    /// mute LED.
+    SetMute(SetMuteProps),
+    /// Sets the colour and brightness of a channel's LED.
+    SetLed(SetLedProps),
+    /// Shows a label, percentage and icon on a channel's display.
+    SetDisplay(SetDisplayProps),
+    RenameSlider(RenameSliderProps),
+    SetSliderAction(SetSliderActionProps),
+    /// Writes the slider configuration to flash, so it outlives a power
+    /// cycle. Renames and actions only live in RAM until then.
+    SaveConfig,
+    /// Switches the device to its bootloader to receive an image. A device
+    /// already holding part of the same image reports how much, so an
+    /// interrupted transfer resumes where it stopped.
+    StartUpdate(StartUpdateProps),
+    UpdateChunk(UpdateChunkProps),
+    /// Asks the device to check the received image and boot it.
+    FinishUpdate,
+    /// Heartbeat, the device answers with a [`CommandsIn::Pong`] carrying
+    /// the same nonce.
+    Ping(u16),
+}
+
+impl CommandsOut {
+    /// Capability the device has to announce before this command is sent.
+    pub fn required_capability(&self) -> Option<Capabilities> {
+        match self {
+            CommandsOut::Hello
+            | CommandsOut::RequestInfo
+            | CommandsOut::SetVolume(_)
+            | CommandsOut::RequestVolume(_) => None,
+            CommandsOut::RequestVolumes | CommandsOut::SetVolumes(_) => Some(Capabilities::BULK),
+            CommandsOut::SetPositions(_) => Some(Capabilities::BULK | Capabilities::HIGH_RES),
+            CommandsOut::SetMute(_) => Some(Capabilities::MUTE),
+            CommandsOut::SetLed(_) => Some(Capabilities::LEDS),
+            CommandsOut::SetDisplay(_) => Some(Capabilities::DISPLAY),
+            CommandsOut::RenameSlider(_)
+            | CommandsOut::SetSliderAction(_)
+            | CommandsOut::SaveConfig => Some(Capabilities::CONFIG),
+            CommandsOut::StartUpdate(_)
+            | CommandsOut::UpdateChunk(_)
+            | CommandsOut::FinishUpdate => Some(Capabilities::UPDATE),
+            CommandsOut::Ping(_) => Some(Capabilities::HEARTBEAT),
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum CommandOut {
+    RequestInfo = 0x01,
+    SetVolume = 0x02,
+    Hello = 0x03,
+    RequestVolume = 0x04,
+    SetMute = 0x05,
+    SetLed = 0x06,
+    SetDisplay = 0x07,
+    RenameSlider = 0x08,
+    SetSliderAction = 0x09,
+    SaveConfig = 0x0A,
+    StartUpdate = 0x0B,
+    UpdateChunk = 0x0C,
+    FinishUpdate = 0x0D,
+    Ping = 0x0E,
+    RequestVolumes = 0x0F,
+    SetVolumes = 0x10,
+    SetPositions = 0x11,
+}
+
+impl TryFrom<u8> for CommandOut {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(CommandOut::RequestInfo),
+            0x02 => Ok(CommandOut::SetVolume),
+            0x03 => Ok(CommandOut::Hello),
+            0x04 => Ok(CommandOut::RequestVolume),
+            0x05 => Ok(CommandOut::SetMute),
+            0x06 => Ok(CommandOut::SetLed),
+            0x07 => Ok(CommandOut::SetDisplay),
+            0x08 => Ok(CommandOut::RenameSlider),
+            0x09 => Ok(CommandOut::SetSliderAction),
+            0x0A => Ok(CommandOut::SaveConfig),
+            0x0B => Ok(CommandOut::StartUpdate),
+            0x0C => Ok(CommandOut::UpdateChunk),
+            0x0D => Ok(CommandOut::FinishUpdate),
+            0x0E => Ok(CommandOut::Ping),
+            0x0F => Ok(CommandOut::RequestVolumes),
+            0x10 => Ok(CommandOut::SetVolumes),
+            0x11 => Ok(CommandOut::SetPositions),
+            _ => Err(()),
+        }
+    }
+}
+
+#[repr(u8)]
+pub enum CommandIn {
+    SendInfo = 0x81,
+    SendVolume = 0x82,
+    Hello = 0x83,
+    Button = 0x84,
+    Encoder = 0x85,
+    UpdateProgress = 0x86,
+    UpdateFinished = 0x87,
+    Pong = 0x88,
+    SendVolumes = 0x89,
+    SendPositions = 0x8A,
+    Ack = 0x90,
+    Nack = 0x91,
+}
+
+impl TryFrom<u8> for CommandIn {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x81 => Ok(CommandIn::SendInfo),
+            0x82 => Ok(CommandIn::SendVolume),
+            0x83 => Ok(CommandIn::Hello),
+            0x84 => Ok(CommandIn::Button),
+            0x85 => Ok(CommandIn::Encoder),
+            0x86 => Ok(CommandIn::UpdateProgress),
+            0x87 => Ok(CommandIn::UpdateFinished),
+            0x88 => Ok(CommandIn::Pong),
+            0x89 => Ok(CommandIn::SendVolumes),
+            0x8A => Ok(CommandIn::SendPositions),
+            0x90 => Ok(CommandIn::Ack),
+            0x91 => Ok(CommandIn::Nack),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DeviceSliderData {
+    pub name: String,
+    pub set_volume_action: String,
+}
+
+/// A push button, numbered by its position in [`DeviceInfo::buttons`]
+/// starting at 1.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DeviceButtonData {
+    pub name: String,
+    /// Default binding for a short press, e.g. `mute:1` or `play-pause`.
+    #[serde(default)]
+    pub action: String,
+    /// Default binding for holding the button.
+    #[serde(default)]
+    pub long_press_action: String,
+}
+
+/// A rotary encoder, numbered by its position in [`DeviceInfo::encoders`]
+/// starting at 1.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DeviceEncoderData {
+    pub name: String,
+    /// Default binding, e.g. `volume:1` or `tracks`.
+    #[serde(default)]
+    pub action: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct DeviceInfo {
+    pub sliders: Vec<DeviceSliderData>,
+    /// Missing on hardware without buttons.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buttons: Vec<DeviceButtonData>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub encoders: Vec<DeviceEncoderData>,
+}
+
+/// Optional features a device announces during the handshake.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities(pub u16);
+
+impl Capabilities {
+    pub const MUTE: Self = Self(1 << 0);
+    pub const LEDS: Self = Self(1 << 1);
+    pub const BUTTONS: Self = Self(1 << 2);
+    pub const DISPLAY: Self = Self(1 << 3);
+    /// Fader positions are reported and taken at full [`MAX_POSITION`]
+    /// resolution, not just in whole percents.
+    pub const HIGH_RES: Self = Self(1 << 4);
+    /// Sliders can be renamed and rebound, and the result saved to flash.
+    pub const CONFIG: Self = Self(1 << 5);
+    /// Firmware can be updated over the link.
+    pub const UPDATE: Self = Self(1 << 6);
+    /// The device answers pings, so a wedged link can be told apart from an
+    /// idle one.
+    pub const HEARTBEAT: Self = Self(1 << 7);
+    /// Every channel can be read and set with a single frame.
+    pub const BULK: Self = Self(1 << 8);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Names of the announced capabilities, for display purposes.
+    pub fn names(self) -> Vec<&'static str> {
+        [
+            (Self::MUTE, "mute"),
+            (Self::LEDS, "leds"),
+            (Self::BUTTONS, "buttons"),
+            (Self::DISPLAY, "display"),
+            (Self::HIGH_RES, "high-res"),
+            (Self::CONFIG, "config"),
+            (Self::UPDATE, "update"),
+            (Self::HEARTBEAT, "heartbeat"),
+            (Self::BULK, "bulk"),
+        ]
+        .into_iter()
+        .filter(|(capability, _)| self.contains(*capability))
+        .map(|(_, name)| name)
+        .collect()
+    }
+}
+
+impl std::ops::BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FirmwareVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl std::fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Reply to [`CommandsOut::Hello`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct HelloInfo {
+    pub protocol_version: u8,
+    pub firmware_version: FirmwareVersion,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandsIn {
+    Hello(HelloInfo),
+    SendInfo(DeviceInfo),
+    SendVolume(VolumeInfo),
+    /// Position of every channel, starting with channel 1. Sent in reply to
+    /// [`CommandsOut::RequestVolumes`] and whenever several faders moved at
+    /// once.
+    SendVolumes(Vec<u8>),
+    /// [`CommandsIn::SendVolumes`] at full resolution, from firmware with
+    /// [`Capabilities::HIGH_RES`].
+    SendPositions(Vec<u16>),
+    Button(ButtonEvent),
+    Encoder(EncoderEvent),
+    /// How many bytes of the image being updated the device holds, i.e. the
+    /// offset of the next chunk it expects.
+    UpdateProgress(u32),
+    /// Whether the received image checked out. The device reboots into it
+    /// right after a success.
+    UpdateFinished(bool),
+    /// Answer to [`CommandsOut::Ping`].
+    Pong(u16),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VolumeInfo {
+    /// 1-based channel number, as on the wire.
+    pub channel: u8,
+    /// Whole percent, sent by every firmware.
+    pub volume: u8,
+    /// Sent along by firmware with [`Capabilities::HIGH_RES`].
+    pub fine_position: Option<u16>,
+}
+
+impl VolumeInfo {
+    /// The fine position if the device sent one, the percent scaled up
+    /// otherwise.
+    pub fn position(&self) -> u16 {
+        self.fine_position
+            .unwrap_or_else(|| percent_position(self.volume))
+    }
+}
+
+/// What happened to a button. A long press is reported while the button is
+/// still held, the release follows later.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEventKind {
+    Press = 0,
+    Release = 1,
+    LongPress = 2,
+}
+
+impl TryFrom<u8> for ButtonEventKind {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ButtonEventKind::Press),
+            1 => Ok(ButtonEventKind::Release),
+            2 => Ok(ButtonEventKind::LongPress),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ButtonEvent {
+    /// 1-based button number, as on the wire.
+    pub button: u8,
+    pub kind: ButtonEventKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncoderEvent {
+    /// 1-based encoder number, as on the wire.
+    pub encoder: u8,
+    /// Detents turned since the last event, positive is clockwise.
+    pub steps: i8,
+}
+
+/// Everything that can go wrong between raw bytes and a [`CommandsIn`].
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// The payload ended before all fields of the command were read.
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    /// The payload carried bytes after the last field of the command.
+    TooLong {
+        expected: usize,
+        actual: usize,
+    },
+    UnknownCommand(u8),
+    /// A known command that is not meant to reach the protocol layer.
+    UnexpectedCommand(u8),
+    BadJson(serde_json::Error),
+    /// Channels are 1-based on the wire, 0 is never valid.
+    BadChannel(u8),
+    BadButtonEvent(u8),
+    /// The COBS encoding of a frame is broken.
+    BadFrame,
+    FrameTooLong,
+    BadChecksum {
+        expected: u16,
+        actual: u16,
+    },
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::Truncated { expected, actual } => {
+                write!(
+                    f,
+                    "payload truncated, expected {expected} bytes but got {actual}"
+                )
+            }
+            ProtocolError::TooLong { expected, actual } => {
+                write!(
+                    f,
+                    "payload too long, expected {expected} bytes but got {actual}"
+                )
+            }
+            ProtocolError::UnknownCommand(command) => write!(f, "unknown command {command:#04X}"),
+            ProtocolError::UnexpectedCommand(command) => {
+                write!(f, "unexpected command {command:#04X}")
+            }
+            ProtocolError::BadJson(e) => write!(f, "invalid JSON: {e}"),
+            ProtocolError::BadChannel(channel) => write!(f, "invalid channel {channel}"),
+            ProtocolError::BadButtonEvent(kind) => write!(f, "invalid button event {kind}"),
+            ProtocolError::BadFrame => write!(f, "malformed frame"),
+            ProtocolError::FrameTooLong => write!(f, "frame exceeded {MAX_FRAME_LEN} bytes"),
+            ProtocolError::BadChecksum { expected, actual } => {
+                write!(
+                    f,
+                    "checksum mismatch, expected {expected:#06X} got {actual:#06X}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// Translates between protocol commands and the bytes carried inside a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Codec;
+
+impl Codec {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn encode(&self, command: CommandsOut) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        match command {
+            CommandsOut::Hello => {
+                buffer.push(CommandOut::Hello as u8);
+                buffer.push(PROTOCOL_VERSION);
+            }
+            CommandsOut::RequestInfo => {
+                buffer.push(CommandOut::RequestInfo as u8);
+                buffer.push(0x00); // channel (unused)
+            }
+            CommandsOut::SetVolume(props) => {
+                buffer.push(CommandOut::SetVolume as u8);
+                buffer.push(props.channel);
+                buffer.push(props.volume);
+                if let Some(position) = props.fine_position {
+                    buffer.extend_from_slice(&position.to_le_bytes());
+                }
+            }
+            CommandsOut::RequestVolume(channel) => {
+                buffer.push(CommandOut::RequestVolume as u8);
+                buffer.push(channel);
+            }
+            CommandsOut::RequestVolumes => {
+                buffer.push(CommandOut::RequestVolumes as u8);
+            }
+            CommandsOut::SetVolumes(volumes) => {
+                buffer.push(CommandOut::SetVolumes as u8);
+                buffer.extend_from_slice(&volumes);
+            }
+            CommandsOut::SetPositions(positions) => {
+                buffer.push(CommandOut::SetPositions as u8);
+                buffer.extend(positions.iter().flat_map(|position| position.to_le_bytes()));
+            }
+            CommandsOut::SetMute(props) => {
+                buffer.push(CommandOut::SetMute as u8);
+                buffer.push(props.channel);
+                buffer.push(props.muted as u8);
+            }
+            CommandsOut::SetLed(props) => {
+                buffer.push(CommandOut::SetLed as u8);
+                buffer.push(props.channel);
+                buffer.extend_from_slice(&[props.color.r, props.color.g, props.color.b]);
+                buffer.push(props.brightness);
+            }
+            CommandsOut::SetDisplay(props) => {
+                let label = truncate_label(&props.label);
+                buffer.push(CommandOut::SetDisplay as u8);
+                buffer.push(props.channel);
+                buffer.push(props.percentage);
+                buffer.push(label.len() as u8);
+                buffer.extend_from_slice(label.as_bytes());
+                if let Some(Icon(rows)) = props.icon {
+                    buffer.extend(rows.iter().flat_map(|row| row.to_be_bytes()));
+                }
+            }
+            CommandsOut::RenameSlider(props) => {
+                buffer.push(CommandOut::RenameSlider as u8);
+                buffer.push(props.channel);
+                buffer.extend_from_slice(props.name.as_bytes());
+            }
+            CommandsOut::SetSliderAction(props) => {
+                buffer.push(CommandOut::SetSliderAction as u8);
+                buffer.push(props.channel);
+                buffer.extend_from_slice(props.action.as_bytes());
+            }
+            CommandsOut::SaveConfig => {
+                buffer.push(CommandOut::SaveConfig as u8);
+            }
+            CommandsOut::StartUpdate(props) => {
+                buffer.push(CommandOut::StartUpdate as u8);
+                buffer.extend_from_slice(&props.size.to_le_bytes());
+                buffer.extend_from_slice(&props.checksum.to_le_bytes());
+            }
+            CommandsOut::UpdateChunk(props) => {
+                buffer.push(CommandOut::UpdateChunk as u8);
+                buffer.extend_from_slice(&props.offset.to_le_bytes());
+                buffer.extend_from_slice(&props.crc.to_le_bytes());
+                buffer.extend_from_slice(&props.data);
+            }
+            CommandsOut::FinishUpdate => {
+                buffer.push(CommandOut::FinishUpdate as u8);
+            }
+            CommandsOut::Ping(nonce) => {
+                buffer.push(CommandOut::Ping as u8);
+                buffer.extend_from_slice(&nonce.to_le_bytes());
+            }
+        }
+
+        buffer
+    }
+
+    pub fn decode(&self, buffer: &[u8]) -> Result<CommandsIn, ProtocolError> {
+        expect_len(buffer, 1)?;
+        let command = buffer[0];
+
+        match CommandIn::try_from(command) {
+            Ok(CommandIn::Hello) => {
+                expect_len(buffer, 7)?;
+                Ok(CommandsIn::Hello(HelloInfo {
+                    protocol_version: buffer[1],
+                    firmware_version: FirmwareVersion {
+                        major: buffer[2],
+                        minor: buffer[3],
+                        patch: buffer[4],
+                    },
+                    capabilities: Capabilities(u16::from_le_bytes([buffer[5], buffer[6]])),
+                }))
+            }
+            Ok(CommandIn::SendInfo) => {
+                // Second byte is the (unused) channel, JSON follows
+                expect_len(buffer, 3)?;
+                let device_info = serde_json::from_slice::<DeviceInfo>(&buffer[2..])
+                    .map_err(ProtocolError::BadJson)?;
+                Ok(CommandsIn::SendInfo(device_info))
+            }
+            Ok(CommandIn::SendVolume) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsIn::SendVolume(VolumeInfo {
+                    channel: channel(buffer[1])?,
+                    volume: buffer[2],
+                    fine_position: fine_position(buffer, 3)?,
+                }))
+            }
+            Ok(CommandIn::SendVolumes) => Ok(CommandsIn::SendVolumes(buffer[1..].to_vec())),
+            Ok(CommandIn::SendPositions) => Ok(CommandsIn::SendPositions(positions(buffer)?)),
+            Ok(CommandIn::Button) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsIn::Button(ButtonEvent {
+                    button: channel(buffer[1])?,
+                    kind: ButtonEventKind::try_from(buffer[2])
+                        .map_err(|_| ProtocolError::BadButtonEvent(buffer[2]))?,
+                }))
+            }
+            Ok(CommandIn::Encoder) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsIn::Encoder(EncoderEvent {
+                    encoder: channel(buffer[1])?,
+                    steps: buffer[2] as i8,
+                }))
+            }
+            Ok(CommandIn::UpdateProgress) => {
+                expect_len(buffer, 5)?;
+                Ok(CommandsIn::UpdateProgress(u32::from_le_bytes([
+                    buffer[1], buffer[2], buffer[3], buffer[4],
+                ])))
+            }
+            Ok(CommandIn::UpdateFinished) => {
+                expect_len(buffer, 2)?;
+                Ok(CommandsIn::UpdateFinished(buffer[1] != 0))
+            }
+            Ok(CommandIn::Pong) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsIn::Pong(u16::from_le_bytes([buffer[1], buffer[2]])))
+            }
+            Ok(CommandIn::Ack | CommandIn::Nack) => Err(ProtocolError::UnexpectedCommand(command)),
+            Err(_) => Err(ProtocolError::UnknownCommand(command)),
+        }
+    }
+
+    /// Device side of [`Codec::decode`], used by the emulator.
+    pub fn encode_in(&self, command: CommandsIn) -> Vec<u8> {
+        let mut buffer = Vec::new();
+
+        match command {
+            CommandsIn::Hello(hello) => {
+                buffer.push(CommandIn::Hello as u8);
+                buffer.push(hello.protocol_version);
+                buffer.push(hello.firmware_version.major);
+                buffer.push(hello.firmware_version.minor);
+                buffer.push(hello.firmware_version.patch);
+                buffer.extend_from_slice(&hello.capabilities.0.to_le_bytes());
+            }
+            CommandsIn::SendInfo(device_info) => {
+                buffer.push(CommandIn::SendInfo as u8);
+                buffer.push(0x00); // channel (unused)
+                buffer.extend(serde_json::to_vec(&device_info).unwrap_or_default());
+            }
+            CommandsIn::SendVolume(volume_info) => {
+                buffer.push(CommandIn::SendVolume as u8);
+                buffer.push(volume_info.channel);
+                buffer.push(volume_info.volume);
+                if let Some(position) = volume_info.fine_position {
+                    buffer.extend_from_slice(&position.to_le_bytes());
+                }
+            }
+            CommandsIn::SendVolumes(volumes) => {
+                buffer.push(CommandIn::SendVolumes as u8);
+                buffer.extend_from_slice(&volumes);
+            }
+            CommandsIn::SendPositions(positions) => {
+                buffer.push(CommandIn::SendPositions as u8);
+                buffer.extend(positions.iter().flat_map(|position| position.to_le_bytes()));
+            }
+            CommandsIn::Button(event) => {
+                buffer.push(CommandIn::Button as u8);
+                buffer.push(event.button);
+                buffer.push(event.kind as u8);
+            }
+            CommandsIn::Encoder(event) => {
+                buffer.push(CommandIn::Encoder as u8);
+                buffer.push(event.encoder);
+                buffer.push(event.steps as u8);
+            }
+            CommandsIn::UpdateProgress(received) => {
+                buffer.push(CommandIn::UpdateProgress as u8);
+                buffer.extend_from_slice(&received.to_le_bytes());
+            }
+            CommandsIn::UpdateFinished(ok) => {
+                buffer.push(CommandIn::UpdateFinished as u8);
+                buffer.push(ok as u8);
+            }
+            CommandsIn::Pong(nonce) => {
+                buffer.push(CommandIn::Pong as u8);
+                buffer.extend_from_slice(&nonce.to_le_bytes());
+            }
+        }
+
+        buffer
+    }
+
+    /// Device side of [`Codec::encode`], used by the emulator.
+    pub fn decode_out(&self, buffer: &[u8]) -> Result<CommandsOut, ProtocolError> {
+        expect_len(buffer, 1)?;
+        let command = buffer[0];
+
+        match CommandOut::try_from(command) {
+            Ok(CommandOut::Hello) => Ok(CommandsOut::Hello),
+            Ok(CommandOut::RequestInfo) => Ok(CommandsOut::RequestInfo),
+            Ok(CommandOut::SetVolume) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsOut::SetVolume(SetVolumeProps {
+                    channel: channel(buffer[1])?,
+                    volume: buffer[2],
+                    fine_position: fine_position(buffer, 3)?,
+                }))
+            }
+            Ok(CommandOut::RequestVolume) => {
+                expect_len(buffer, 2)?;
+                Ok(CommandsOut::RequestVolume(channel(buffer[1])?))
+            }
+            Ok(CommandOut::RequestVolumes) => Ok(CommandsOut::RequestVolumes),
+            Ok(CommandOut::SetVolumes) => Ok(CommandsOut::SetVolumes(buffer[1..].to_vec())),
+            Ok(CommandOut::SetPositions) => Ok(CommandsOut::SetPositions(positions(buffer)?)),
+            Ok(CommandOut::SetMute) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsOut::SetMute(SetMuteProps {
+                    channel: channel(buffer[1])?,
+                    muted: buffer[2] != 0,
+                }))
+            }
+            Ok(CommandOut::SetLed) => {
+                expect_len(buffer, 6)?;
+                Ok(CommandsOut::SetLed(SetLedProps {
+                    channel: channel(buffer[1])?,
+                    color: Rgb::new(buffer[2], buffer[3], buffer[4]),
+                    brightness: buffer[5],
+                }))
+            }
+            Ok(CommandOut::SetDisplay) => {
+                expect_len(buffer, 4)?;
+                let label_end = 4 + buffer[3] as usize;
+                expect_len(buffer, label_end)?;
+                let icon = match &buffer[label_end..] {
+                    [] => None,
+                    rows if rows.len() == ICON_SIZE * 2 => {
+                        let mut icon = [0; ICON_SIZE];
+                        for (row, bytes) in icon.iter_mut().zip(rows.chunks_exact(2)) {
+                            *row = u16::from_be_bytes([bytes[0], bytes[1]]);
+                        }
+                        Some(Icon(icon))
+                    }
+                    _ => {
+                        return Err(ProtocolError::Truncated {
+                            expected: label_end + ICON_SIZE * 2,
+                            actual: buffer.len(),
+                        });
+                    }
+                };
+                Ok(CommandsOut::SetDisplay(SetDisplayProps {
+                    channel: channel(buffer[1])?,
+                    label: String::from_utf8_lossy(&buffer[4..label_end]).into_owned(),
+                    percentage: buffer[2],
+                    icon,
+                }))
+            }
+            Ok(CommandOut::RenameSlider) => {
+                expect_len(buffer, 2)?;
+                Ok(CommandsOut::RenameSlider(RenameSliderProps {
+                    channel: channel(buffer[1])?,
+                    name: String::from_utf8_lossy(&buffer[2..]).into_owned(),
+                }))
+            }
+            Ok(CommandOut::SetSliderAction) => {
+                expect_len(buffer, 2)?;
+                Ok(CommandsOut::SetSliderAction(SetSliderActionProps {
+                    channel: channel(buffer[1])?,
+                    action: String::from_utf8_lossy(&buffer[2..]).into_owned(),
+                }))
+            }
+            Ok(CommandOut::SaveConfig) => Ok(CommandsOut::SaveConfig),
+            Ok(CommandOut::StartUpdate) => {
+                expect_len(buffer, 9)?;
+                Ok(CommandsOut::StartUpdate(StartUpdateProps {
+                    size: u32::from_le_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]),
+                    checksum: u32::from_le_bytes([buffer[5], buffer[6], buffer[7], buffer[8]]),
+                }))
+            }
+            Ok(CommandOut::UpdateChunk) => {
+                expect_len(buffer, 7)?;
+                Ok(CommandsOut::UpdateChunk(UpdateChunkProps {
+                    offset: u32::from_le_bytes([buffer[1], buffer[2], buffer[3], buffer[4]]),
+                    crc: u16::from_le_bytes([buffer[5], buffer[6]]),
+                    data: buffer[7..].to_vec(),
+                }))
+            }
+            Ok(CommandOut::FinishUpdate) => Ok(CommandsOut::FinishUpdate),
+            Ok(CommandOut::Ping) => {
+                expect_len(buffer, 3)?;
+                Ok(CommandsOut::Ping(u16::from_le_bytes([
+                    buffer[1], buffer[2],
+                ])))
+            }
+            Err(_) => Err(ProtocolError::UnknownCommand(command)),
+        }
+    }
+}
+
+/// Longest prefix of `label` that fits [`MAX_LABEL_LEN`] without splitting a
+/// character.
+fn truncate_label(label: &str) -> &str {
+    let mut end = label.len().min(MAX_LABEL_LEN);
+    while !label.is_char_boundary(end) {
+        end -= 1;
+    }
+    &label[..end]
+}
+
+fn expect_len(buffer: &[u8], expected: usize) -> Result<(), ProtocolError> {
+    if buffer.len() < expected {
+        return Err(ProtocolError::Truncated {
+            expected,
+            actual: buffer.len(),
+        });
+    }
+    Ok(())
+}
+
+/// The optional little endian position trailing a volume at `offset`.
+fn fine_position(buffer: &[u8], offset: usize) -> Result<Option<u16>, ProtocolError> {
+    match &buffer[offset..] {
+        [] => Ok(None),
+        [low, high] => Ok(Some(u16::from_le_bytes([*low, *high]))),
+        [_] => Err(ProtocolError::Truncated {
+            expected: offset + 2,
+            actual: buffer.len(),
+        }),
+        _ => Err(ProtocolError::TooLong {
+            expected: offset + 2,
+            actual: buffer.len(),
+        }),
+    }
+}
+
+/// Little endian positions following the command byte.
+fn positions(buffer: &[u8]) -> Result<Vec<u16>, ProtocolError> {
+    let pairs = buffer[1..].chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return Err(ProtocolError::Truncated {
+            expected: buffer.len() + 1,
+            actual: buffer.len(),
+        });
+    }
+    Ok(pairs
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect())
+}
+
+fn channel(channel: u8) -> Result<u8, ProtocolError> {
+    if channel == 0 {
+        return Err(ProtocolError::BadChannel(channel));
+    }
+    Ok(channel)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every command the app sends, next to its exact bytes on the wire.
+    fn out_cases() -> Vec<(CommandsOut, Vec<u8>)> {
+        let mut icon = [0; ICON_SIZE];
+        icon[0] = 0x8001;
+        let mut icon_bytes = vec![0x80, 0x01];
+        icon_bytes.resize(ICON_SIZE * 2, 0);
+
+        vec![
+            (CommandsOut::Hello, vec![0x03, 0x01]),
+            (CommandsOut::RequestInfo, vec![0x01, 0x00]),
+            (
+                CommandsOut::SetVolume(SetVolumeProps {
+                    channel: 2,
+                    volume: 50,
+                    fine_position: None,
+                }),
+                vec![0x02, 2, 50],
+            ),
+            (
+                CommandsOut::SetVolume(SetVolumeProps {
+                    channel: 2,
+                    volume: 50,
+                    fine_position: Some(0x8012),
+                }),
+                vec![0x02, 2, 50, 0x12, 0x80],
+            ),
+            (CommandsOut::RequestVolume(3), vec![0x04, 3]),
+            (CommandsOut::RequestVolumes, vec![0x0F]),
+            (
+                CommandsOut::SetVolumes(vec![0, 50, 100]),
+                vec![0x10, 0, 50, 100],
+            ),
+            (
+                CommandsOut::SetPositions(vec![0x0102, 0xFFFF]),
+                vec![0x11, 0x02, 0x01, 0xFF, 0xFF],
+            ),
+            (
+                CommandsOut::SetMute(SetMuteProps {
+                    channel: 1,
+                    muted: true,
+                }),
+                vec![0x05, 1, 1],
+            ),
+            (
+                CommandsOut::SetLed(SetLedProps {
+                    channel: 4,
+                    color: Rgb::new(0xFF, 0x80, 0x00),
+                    brightness: 24,
+                }),
+                vec![0x06, 4, 0xFF, 0x80, 0x00, 24],
+            ),
+            (
+                CommandsOut::SetDisplay(SetDisplayProps {
+                    channel: 1,
+                    label: "Mic".to_string(),
+                    percentage: 75,
+                    icon: None,
+                }),
+                vec![0x07, 1, 75, 3, b'M', b'i', b'c'],
+            ),
+            (
+                CommandsOut::SetDisplay(SetDisplayProps {
+                    channel: 1,
+                    label: "Mic".to_string(),
+                    percentage: 75,
+                    icon: Some(Icon(icon)),
+                }),
+                [vec![0x07, 1, 75, 3, b'M', b'i', b'c'], icon_bytes].concat(),
+            ),
+            (
+                CommandsOut::RenameSlider(RenameSliderProps {
+                    channel: 2,
+                    name: "Music".to_string(),
+                }),
+                [&[0x08, 2][..], b"Music"].concat(),
+            ),
+            (
+                CommandsOut::SetSliderAction(SetSliderActionProps {
+                    channel: 2,
+                    action: "app:spotify".to_string(),
+                }),
+                [&[0x09, 2][..], b"app:spotify"].concat(),
+            ),
+            (CommandsOut::SaveConfig, vec![0x0A]),
+            (
+                CommandsOut::StartUpdate(StartUpdateProps {
+                    size: 0x0001_0000,
+                    checksum: 0xCBF4_3926,
+                }),
+                vec![0x0B, 0x00, 0x00, 0x01, 0x00, 0x26, 0x39, 0xF4, 0xCB],
+            ),
+            (
+                CommandsOut::UpdateChunk(UpdateChunkProps {
+                    offset: 0x0400,
+                    crc: 0x29B1,
+                    data: vec![0xDE, 0xAD],
+                }),
+                vec![0x0C, 0x00, 0x04, 0x00, 0x00, 0xB1, 0x29, 0xDE, 0xAD],
+            ),
+            (CommandsOut::FinishUpdate, vec![0x0D]),
+            (CommandsOut::Ping(0x1234), vec![0x0E, 0x34, 0x12]),
+        ]
+    }
+
+    /// Every command the device sends, next to its exact bytes on the wire.
+    fn in_cases() -> Vec<(CommandsIn, Vec<u8>)> {
+        vec![
+            (
+                CommandsIn::Hello(HelloInfo {
+                    protocol_version: 1,
+                    firmware_version: FirmwareVersion {
+                        major: 0,
+                        minor: 2,
+                        patch: 10,
+                    },
+                    capabilities: Capabilities::MUTE | Capabilities::BULK,
+                }),
+                vec![0x83, 1, 0, 2, 10, 0x01, 0x01],
+            ),
+            (
+                CommandsIn::SendInfo(DeviceInfo {
+                    sliders: vec![DeviceSliderData {
+                        name: "Master".to_string(),
+                        set_volume_action: "master".to_string(),
+                    }],
+                    ..Default::default()
+                }),
+                [
+                    &[0x81, 0x00][..],
+                    br#"{"sliders":[{"name":"Master","set_volume_action":"master"}]}"#,
+                ]
+                .concat(),
+            ),
+            (
+                CommandsIn::SendVolume(VolumeInfo {
+                    channel: 1,
+                    volume: 100,
+                    fine_position: None,
+                }),
+                vec![0x82, 1, 100],
+            ),
+            (
+                CommandsIn::SendVolume(VolumeInfo {
+                    channel: 1,
+                    volume: 100,
+                    fine_position: Some(0xFFFF),
+                }),
+                vec![0x82, 1, 100, 0xFF, 0xFF],
+            ),
+            (CommandsIn::SendVolumes(vec![10, 20]), vec![0x89, 10, 20]),
+            (
+                CommandsIn::SendPositions(vec![0x0A0B]),
+                vec![0x8A, 0x0B, 0x0A],
+            ),
+            (
+                CommandsIn::Button(ButtonEvent {
+                    button: 2,
+                    kind: ButtonEventKind::LongPress,
+                }),
+                vec![0x84, 2, 2],
+            ),
+            (
+                CommandsIn::Encoder(EncoderEvent {
+                    encoder: 1,
+                    steps: -2,
+                }),
+                vec![0x85, 1, 0xFE],
+            ),
+            (
+                CommandsIn::UpdateProgress(0x0001_0400),
+                vec![0x86, 0x00, 0x04, 0x01, 0x00],
+            ),
+            (CommandsIn::UpdateFinished(true), vec![0x87, 1]),
+            (CommandsIn::Pong(0x1234), vec![0x88, 0x34, 0x12]),
+        ]
+    }
+
+    #[test]
+    fn outgoing_commands_match_wire_format() {
+        let codec = Codec::new();
+        for (command, bytes) in out_cases() {
+            assert_eq!(codec.encode(command.clone()), bytes, "{command:?}");
+            assert_eq!(codec.decode_out(&bytes).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn incoming_commands_match_wire_format() {
+        let codec = Codec::new();
+        for (command, bytes) in in_cases() {
+            assert_eq!(codec.encode_in(command.clone()), bytes, "{command:?}");
+            assert_eq!(codec.decode(&bytes).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn malformed_commands_are_rejected() {
+        let codec = Codec::new();
+        assert!(matches!(
+            codec.decode(&[]),
+            Err(ProtocolError::Truncated {
+                expected: 1,
+                actual: 0
+            })
+        ));
+        assert!(matches!(
+            codec.decode(&[0x82, 1]),
+            Err(ProtocolError::Truncated {
+                expected: 3,
+                actual: 2
+            })
+        ));
+        assert!(matches!(
+            codec.decode(&[0x82, 1, 50, 0x12]),
+            Err(ProtocolError::Truncated { .. })
+        ));
+        assert!(matches!(
+            codec.decode(&[0x8A, 0x01, 0x02, 0x03]),
+            Err(ProtocolError::Truncated { .. })
+        ));
+        assert!(matches!(
+            codec.decode(&[0x82, 0, 50]),
+            Err(ProtocolError::BadChannel(0))
+        ));
+        assert!(matches!(
+            codec.decode(&[0x84, 1, 7]),
+            Err(ProtocolError::BadButtonEvent(7))
+        ));
+        assert!(matches!(
+            codec.decode(&[0x81, 0x00, b'{']),
+            Err(ProtocolError::BadJson(_))
+        ));
+        assert!(matches!(
+            codec.decode(&[0x90, 0]),
+            Err(ProtocolError::UnexpectedCommand(0x90))
+        ));
+        assert!(matches!(
+            codec.decode(&[0x7F]),
+            Err(ProtocolError::UnknownCommand(0x7F))
+        ));
+    }
+
+    #[test]
+    fn overlong_volumes_are_rejected() {
+        let codec = Codec::new();
+        assert!(matches!(
+            codec.decode(&[0x82, 1, 50, 0x34, 0x12, 0xFF]),
+            Err(ProtocolError::TooLong {
+                expected: 5,
+                actual: 6
+            })
+        ));
+        assert!(matches!(
+            codec.decode_out(&[0x02, 1, 50, 0x34, 0x12, 0, 0]),
+            Err(ProtocolError::TooLong {
+                expected: 5,
+                actual: 7
+            })
+        ));
+    }
+
+    #[test]
+    fn display_labels_are_cut_on_char_boundaries() {
+        assert_eq!(truncate_label("Short"), "Short");
+        assert_eq!(truncate_label("Sixteen bytes ok"), "Sixteen bytes ok");
+        assert_eq!(truncate_label("Seventeen bytes!!"), "Seventeen bytes!");
+        // "é" is two bytes and would straddle the limit
+        assert_eq!(truncate_label("Fifteen bytes!!é"), "Fifteen bytes!!");
+    }
+
+    #[test]
+    fn percent_and_position_scale() {
+        assert_eq!(percent_position(0), 0);
+        assert_eq!(percent_position(100), MAX_POSITION);
+        assert_eq!(percent_position(200), MAX_POSITION);
+        assert_eq!(position_percent(MAX_POSITION), 100);
+        for percent in 0..=100 {
+            assert_eq!(position_percent(percent_position(percent)), percent);
+        }
+    }
+}
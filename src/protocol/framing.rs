@@ -0,0 +1,109 @@
+use crate::protocol::ProtocolError;
+
+/// Byte that terminates every COBS encoded frame on the wire.
+pub const FRAME_DELIMITER: u8 = 0x00;
+/// Upper bound for a single encoded frame, anything longer is dropped.
+pub const MAX_FRAME_LEN: usize = 4096;
+
+/// Encodes `data` with COBS and appends the frame delimiter, so payload bytes
+/// can never be mistaken for the end of a frame.
+pub fn encode_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_index = 0;
+    let mut code: u8 = 1;
+    frame.push(0);
+
+    for &byte in data {
+        if byte == 0 {
+            frame[code_index] = code;
+            code_index = frame.len();
+            frame.push(0);
+            code = 1;
+            continue;
+        }
+
+        frame.push(byte);
+        code += 1;
+        if code == 0xFF {
+            frame[code_index] = code;
+            code_index = frame.len();
+            frame.push(0);
+            code = 1;
+        }
+    }
+
+    frame[code_index] = code;
+    frame.push(FRAME_DELIMITER);
+    frame
+}
+
+/// Decodes a single COBS frame, without its trailing delimiter.
+pub fn decode_frame(frame: &[u8]) -> Result<Vec<u8>, ProtocolError> {
+    let mut data = Vec::with_capacity(frame.len());
+    let mut index = 0;
+
+    while index < frame.len() {
+        let code = frame[index] as usize;
+        if code == 0 {
+            return Err(ProtocolError::BadFrame);
+        }
+        index += 1;
+
+        let end = index + code - 1;
+        if end > frame.len() {
+            return Err(ProtocolError::BadFrame);
+        }
+        data.extend_from_slice(&frame[index..end]);
+        index = end;
+
+        if code < 0xFF && index < frame.len() {
+            data.push(0);
+        }
+    }
+
+    Ok(data)
+}
+
+/// Streaming decoder that collects bytes from the port until a frame
+/// delimiter shows up and then hands back the decoded payload.
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    overflowed: bool,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds one byte into the decoder. Returns `Some` once a frame has been
+    /// completed, empty frames between two delimiters are skipped.
+    pub fn push(&mut self, byte: u8) -> Option<Result<Vec<u8>, ProtocolError>> {
+        if byte != FRAME_DELIMITER {
+            if self.buffer.len() >= MAX_FRAME_LEN {
+                self.buffer.clear();
+                self.overflowed = true;
+            }
+            if !self.overflowed {
+                self.buffer.push(byte);
+            }
+            return None;
+        }
+
+        if std::mem::take(&mut self.overflowed) {
+            return Some(Err(ProtocolError::FrameTooLong));
+        }
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let frame = std::mem::take(&mut self.buffer);
+        Some(decode_frame(&frame))
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        self.overflowed = false;
+    }
+}
@@ -0,0 +1,197 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::CommandsOut;
+
+/// How often a connected device is pinged.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+/// How many pings in a row may go unanswered before the link is declared
+/// dead.
+pub const MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// Round-trip times measured with pings, since the device connected.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub last: Option<Duration>,
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    /// Sum of every round trip, see [`LatencyStats::average`].
+    pub total: Duration,
+    pub pongs: u32,
+    /// Pings that were never answered.
+    pub missed: u32,
+}
+
+impl LatencyStats {
+    pub fn average(&self) -> Option<Duration> {
+        (self.pongs > 0).then(|| self.total / self.pongs)
+    }
+
+    fn record(&mut self, round_trip: Duration) {
+        self.last = Some(round_trip);
+        self.min = Some(self.min.map_or(round_trip, |min| min.min(round_trip)));
+        self.max = Some(self.max.map_or(round_trip, |max| max.max(round_trip)));
+        self.total += round_trip;
+        self.pongs += 1;
+    }
+}
+
+/// Liveness check on top of the link. Reads simply time out on a wedged
+/// device, so it is pinged every [`HEARTBEAT_INTERVAL`] and given up on
+/// after [`MAX_MISSED_HEARTBEATS`] pings in a row went unanswered. Only one
+/// ping is in flight at a time, a pong arriving after the next ping went out
+/// counts as missed.
+#[derive(Default)]
+pub struct Heartbeat {
+    next_nonce: u16,
+    /// Nonce of the unanswered ping and when it was sent.
+    waiting: Option<(u16, Instant)>,
+    missed_in_row: u32,
+    pub stats: LatencyStats,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The next ping to send, `None` once the device stopped answering.
+    pub fn ping(&mut self, now: Instant) -> Option<CommandsOut> {
+        if self.waiting.take().is_some() {
+            self.missed_in_row += 1;
+            self.stats.missed += 1;
+        }
+        if self.is_dead() {
+            return None;
+        }
+
+        let nonce = self.next_nonce;
+        self.next_nonce = self.next_nonce.wrapping_add(1);
+        self.waiting = Some((nonce, now));
+        Some(CommandsOut::Ping(nonce))
+    }
+
+    /// Records the round trip if `nonce` answers the ping in flight, returns
+    /// whether it did.
+    pub fn pong(&mut self, nonce: u16, now: Instant) -> bool {
+        match self.waiting {
+            Some((waiting, sent_at)) if waiting == nonce => {
+                self.waiting = None;
+                self.missed_in_row = 0;
+                self.stats.record(now.duration_since(sent_at));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.missed_in_row >= MAX_MISSED_HEARTBEATS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonce(ping: Option<CommandsOut>) -> u16 {
+        match ping {
+            Some(CommandsOut::Ping(nonce)) => nonce,
+            ping => panic!("expected a ping, got {:?}", ping),
+        }
+    }
+
+    #[test]
+    fn missed_pongs_are_counted() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new();
+        nonce(heartbeat.ping(start));
+        nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL));
+        assert_eq!(heartbeat.missed_in_row, 1);
+        assert_eq!(heartbeat.stats.missed, 1);
+        assert!(!heartbeat.is_dead());
+    }
+
+    #[test]
+    fn device_dies_after_the_last_allowed_miss() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new();
+        nonce(heartbeat.ping(start));
+        for missed in 1..MAX_MISSED_HEARTBEATS {
+            nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL * missed));
+            assert!(!heartbeat.is_dead(), "dead after {} misses", missed);
+        }
+
+        assert_eq!(
+            heartbeat.ping(start + HEARTBEAT_INTERVAL * MAX_MISSED_HEARTBEATS),
+            None
+        );
+        assert!(heartbeat.is_dead());
+        assert_eq!(heartbeat.stats.missed, MAX_MISSED_HEARTBEATS);
+        assert_eq!(heartbeat.ping(start + HEARTBEAT_INTERVAL * 10), None);
+    }
+
+    #[test]
+    fn pongs_reset_the_misses() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new();
+        nonce(heartbeat.ping(start));
+        nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL));
+        let answered = nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL * 2));
+        assert_eq!(heartbeat.missed_in_row, 2);
+
+        assert!(heartbeat.pong(answered, start + HEARTBEAT_INTERVAL * 2));
+        assert_eq!(heartbeat.missed_in_row, 0);
+        // The answered ping is not counted again
+        nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL * 3));
+        assert_eq!(heartbeat.missed_in_row, 0);
+        assert_eq!(heartbeat.stats.missed, 2);
+    }
+
+    #[test]
+    fn stale_and_unknown_pongs_are_ignored() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new();
+        let stale = nonce(heartbeat.ping(start));
+        let current = nonce(heartbeat.ping(start + HEARTBEAT_INTERVAL));
+        assert_ne!(stale, current);
+
+        assert!(!heartbeat.pong(stale, start + HEARTBEAT_INTERVAL));
+        assert!(!heartbeat.pong(current.wrapping_add(1), start + HEARTBEAT_INTERVAL));
+        assert_eq!(heartbeat.missed_in_row, 1);
+        assert_eq!(heartbeat.stats.pongs, 0);
+
+        assert!(heartbeat.pong(current, start + HEARTBEAT_INTERVAL));
+        // Answered already
+        assert!(!heartbeat.pong(current, start + HEARTBEAT_INTERVAL));
+        assert_eq!(heartbeat.stats.pongs, 1);
+    }
+
+    #[test]
+    fn pongs_without_a_ping_are_ignored() {
+        let mut heartbeat = Heartbeat::new();
+        assert!(!heartbeat.pong(0, Instant::now()));
+        assert_eq!(heartbeat.stats, LatencyStats::default());
+    }
+
+    #[test]
+    fn round_trips_are_summed_up() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new();
+        assert_eq!(heartbeat.stats.average(), None);
+
+        for (sent, round_trip) in [(0, 30), (1000, 10), (2000, 50)] {
+            let sent = start + Duration::from_millis(sent);
+            let nonce = nonce(heartbeat.ping(sent));
+            assert!(heartbeat.pong(nonce, sent + Duration::from_millis(round_trip)));
+        }
+
+        let stats = heartbeat.stats;
+        assert_eq!(stats.last, Some(Duration::from_millis(50)));
+        assert_eq!(stats.min, Some(Duration::from_millis(10)));
+        assert_eq!(stats.max, Some(Duration::from_millis(50)));
+        assert_eq!(stats.total, Duration::from_millis(90));
+        assert_eq!(stats.pongs, 3);
+        assert_eq!(stats.average(), Some(Duration::from_millis(30)));
+        assert_eq!(stats.missed, 0);
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use futures_channel::mpsc::UnboundedReceiver;
+use futures_lite::StreamExt;
+use smol::Timer;
+
+use crate::protocol::{
+    Codec, CommandsIn, CommandsOut, FrameDecoder, Link, LinkStats, ProtocolError,
+};
+
+/// How long a single read blocks, bounds how quickly the reader notices that
+/// its session is over.
+pub const READ_TIMEOUT: Duration = Duration::from_millis(100);
+/// Failed reads in a row after which the device is given up on.
+pub const MAX_READ_ERRORS: u32 = 5;
+/// Pause after a failed read, so a port that fails instantly does not spin.
+pub const READ_ERROR_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Whether a read or write error means the device is gone rather than a
+/// transient hiccup.
+///
+/// serialport errors carry no OS code, an unplugged device shows up there as
+/// a plain `Other` error. [`read_loop`] catches those by their repetition.
+pub fn is_disconnect(error: &io::Error) -> bool {
+    use std::io::ErrorKind;
+
+    match error.kind() {
+        ErrorKind::BrokenPipe | ErrorKind::NotConnected | ErrorKind::UnexpectedEof => true,
+        // EIO, ENXIO and ENODEV are what Linux reports for an unplugged tty
+        _ => matches!(error.raw_os_error(), Some(5 | 6 | 19)),
+    }
+}
+
+enum WriterEvent {
+    Command(CommandsOut),
+    Closed,
+    RetransmissionDue,
+}
+
+/// Writes queued commands until every sender of `commands` is dropped.
+///
+/// Sleeps on the channel and, while frames wait for an acknowledgement, on
+/// the next retransmission deadline, so an idle link costs no CPU. A frame
+/// the device NACKs is resent the next time the writer wakes up. Returns an
+/// error once the device is gone.
+pub async fn write_loop(
+    mut writer: impl Write,
+    link: &Mutex<Link>,
+    mut commands: UnboundedReceiver<CommandsOut>,
+    mut on_stats: impl FnMut(LinkStats),
+) -> io::Result<()> {
+    let codec = Codec::new();
+    let mut last_stats = LinkStats::default();
+
+    loop {
+        let deadline = link.lock().unwrap().next_retransmission();
+        let next_command = async {
+            match commands.next().await {
+                Some(command) => WriterEvent::Command(command),
+                None => WriterEvent::Closed,
+            }
+        };
+        let event = match deadline {
+            Some(deadline) => {
+                smol::future::or(next_command, async {
+                    Timer::at(deadline).await;
+                    WriterEvent::RetransmissionDue
+                })
+                .await
+            }
+            None => next_command.await,
+        };
+
+        match event {
+            WriterEvent::Command(command) => {
+                match &command {
+                    // A kilobyte of image per chunk, an update would flood the log
+                    CommandsOut::UpdateChunk(chunk) => println!(
+                        "Sending command: UpdateChunk at {} ({} bytes)",
+                        chunk.offset,
+                        chunk.data.len()
+                    ),
+                    command => println!("Sending command: {:?}", command),
+                }
+                let frame = link.lock().unwrap().wrap(&codec.encode(command));
+                write_frame(&mut writer, &frame)?;
+            }
+            WriterEvent::Closed => return Ok(()),
+            WriterEvent::RetransmissionDue => {}
+        }
+
+        // Resend whatever the device did not acknowledge in time
+        let retransmissions = link.lock().unwrap().due_retransmissions();
+        for frame in retransmissions {
+            write_frame(&mut writer, &frame)?;
+        }
+
+        publish_stats(link, &mut last_stats, &mut on_stats);
+    }
+}
+
+fn write_frame(writer: &mut impl Write, frame: &[u8]) -> io::Result<()> {
+    match writer.write_all(frame).and_then(|_| writer.flush()) {
+        Err(e) if is_disconnect(&e) => Err(e),
+        Err(e) => {
+            // The frame is resent if it was lost, no reason to give up on the device
+            eprintln!("Failed to send frame: {}", e);
+            Ok(())
+        }
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Everything between raw bytes from the device and commands: COBS framing,
+/// the link layer and the codec. No input makes it panic, the fuzz target in
+/// `fuzz/` and the property tests in `tests/` hold it to that.
+#[derive(Default)]
+pub struct Inbound {
+    decoder: FrameDecoder,
+    codec: Codec,
+}
+
+impl Inbound {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds bytes as they were read, handing every completed command, or the
+    /// reason a frame was dropped, to `on_command`. Partial frames are kept
+    /// for the next call.
+    pub fn feed(
+        &mut self,
+        link: &mut Link,
+        bytes: &[u8],
+        mut on_command: impl FnMut(Result<CommandsIn, ProtocolError>),
+    ) {
+        for &byte in bytes {
+            let frame = match self.decoder.push(byte) {
+                Some(Ok(frame)) => frame,
+                Some(Err(e)) => {
+                    link.stats.framing_errors += 1;
+                    on_command(Err(e));
+                    continue;
+                }
+                None => continue,
+            };
+
+            let payload = match link.unwrap(&frame) {
+                Ok(Some(payload)) => payload,
+                Ok(None) => continue,
+                Err(e) => {
+                    on_command(Err(e));
+                    continue;
+                }
+            };
+
+            let command = self.codec.decode(&payload);
+            if command.is_err() {
+                link.stats.decode_errors += 1;
+            }
+            on_command(command);
+        }
+    }
+}
+
+/// Reads and decodes frames until `closed` is set, handing every command, or
+/// the reason a frame was dropped, to `on_command`. Gives up after
+/// [`MAX_READ_ERRORS`] failed reads in a row.
+///
+/// `reader` must have a read timeout, [`READ_TIMEOUT`] is what the serial
+/// transport uses. Returns an error once the device is gone.
+pub fn read_loop(
+    mut reader: impl Read,
+    link: &Mutex<Link>,
+    closed: &AtomicBool,
+    mut on_command: impl FnMut(Result<CommandsIn, ProtocolError>),
+    mut on_stats: impl FnMut(LinkStats),
+) -> io::Result<()> {
+    let mut read_buffer = [0u8; 1024];
+    let mut inbound = Inbound::new();
+    let mut last_stats = LinkStats::default();
+    let mut errors = 0;
+
+    while !closed.load(Ordering::Relaxed) {
+        let received = match reader.read(&mut read_buffer) {
+            // End of file, only sockets report this when the peer went away
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => &read_buffer[..n],
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock
+                ) =>
+            {
+                errors = 0;
+                continue;
+            }
+            Err(e) if is_disconnect(&e) => return Err(e),
+            Err(e) => {
+                errors += 1;
+                if errors >= MAX_READ_ERRORS {
+                    return Err(e);
+                }
+                eprintln!("Read error: {}", e);
+                thread::sleep(READ_ERROR_BACKOFF);
+                continue;
+            }
+        };
+        errors = 0;
+
+        inbound.feed(&mut link.lock().unwrap(), received, &mut on_command);
+
+        publish_stats(link, &mut last_stats, &mut on_stats);
+    }
+
+    Ok(())
+}
+
+fn publish_stats(
+    link: &Mutex<Link>,
+    last_stats: &mut LinkStats,
+    on_stats: &mut impl FnMut(LinkStats),
+) {
+    let stats = link.lock().unwrap().stats;
+    if stats != *last_stats {
+        *last_stats = stats;
+        on_stats(stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A port that fails every read with `kind`.
+    struct FailingReader {
+        kind: io::ErrorKind,
+        reads: u32,
+    }
+
+    impl Read for FailingReader {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            self.reads += 1;
+            Err(self.kind.into())
+        }
+    }
+
+    fn run(reader: &mut FailingReader, closed: &AtomicBool) -> io::Result<()> {
+        read_loop(reader, &Mutex::new(Link::new()), closed, |_| {}, |_| {})
+    }
+
+    #[test]
+    fn repeated_read_errors_end_the_loop() {
+        let mut reader = FailingReader {
+            kind: io::ErrorKind::Other,
+            reads: 0,
+        };
+        let error = run(&mut reader, &AtomicBool::new(false)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_eq!(reader.reads, MAX_READ_ERRORS);
+    }
+
+    #[test]
+    fn broken_pipe_ends_the_loop_at_once() {
+        let mut reader = FailingReader {
+            kind: io::ErrorKind::BrokenPipe,
+            reads: 0,
+        };
+        assert!(run(&mut reader, &AtomicBool::new(false)).is_err());
+        assert_eq!(reader.reads, 1);
+    }
+
+    #[test]
+    fn timeouts_are_not_errors() {
+        let closed = AtomicBool::new(false);
+        let mut reader = FailingReader {
+            kind: io::ErrorKind::TimedOut,
+            reads: 0,
+        };
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                closed.store(true, Ordering::Relaxed);
+            });
+            assert!(run(&mut reader, &closed).is_ok());
+        });
+        assert!(reader.reads > MAX_READ_ERRORS);
+    }
+}
@@ -0,0 +1,328 @@
+use std::time::{Duration, Instant};
+
+use crate::protocol::{CommandIn, ProtocolError, encode_frame};
+
+/// How long to wait for the device to acknowledge a frame before resending it.
+pub const ACK_TIMEOUT: Duration = Duration::from_millis(200);
+/// How many times a frame is resent before it is given up on.
+pub const MAX_RETRIES: u8 = 3;
+
+/// Counters describing the health of the serial link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    pub frames_sent: u32,
+    pub frames_received: u32,
+    pub acks: u32,
+    pub nacks: u32,
+    pub retransmissions: u32,
+    pub dropped: u32,
+    pub crc_errors: u32,
+    pub framing_errors: u32,
+    pub decode_errors: u32,
+}
+
+struct PendingFrame {
+    seq: u8,
+    frame: Vec<u8>,
+    sent_at: Instant,
+    attempts: u8,
+    nacked: bool,
+}
+
+/// Reliability layer on top of the COBS framing.
+///
+/// Every frame is laid out as `[seq, payload.., crc_lo, crc_hi]`. Frames sent by
+/// the host must be acknowledged by the device with an `Ack`/`Nack` frame that
+/// carries the sequence number, unacknowledged frames are resent after
+/// [`ACK_TIMEOUT`] until [`MAX_RETRIES`] is exhausted. Frames coming from the
+/// device are only checked for corruption.
+#[derive(Default)]
+pub struct Link {
+    next_seq: u8,
+    pending: Vec<PendingFrame>,
+    pub stats: LinkStats,
+}
+
+impl Link {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `payload` into a frame ready to be written to the port and keeps
+    /// it around until the device acknowledges it.
+    pub fn wrap(&mut self, payload: &[u8]) -> Vec<u8> {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        let frame = link_frame(seq, payload);
+        self.pending.push(PendingFrame {
+            seq,
+            frame: frame.clone(),
+            sent_at: Instant::now(),
+            attempts: 0,
+            nacked: false,
+        });
+        self.stats.frames_sent += 1;
+
+        frame
+    }
+
+    /// Checks a decoded frame coming from the device. Acknowledgements are
+    /// consumed here and yield `Ok(None)`, anything else is handed back
+    /// without its sequence number and checksum.
+    pub fn unwrap(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, ProtocolError> {
+        let (_, payload) = split_link_frame(data).inspect_err(|_| self.stats.crc_errors += 1)?;
+        self.stats.frames_received += 1;
+
+        match CommandIn::try_from(payload[0]) {
+            Ok(CommandIn::Ack) if payload.len() >= 2 => {
+                self.stats.acks += 1;
+                self.pending.retain(|frame| frame.seq != payload[1]);
+                Ok(None)
+            }
+            Ok(CommandIn::Nack) if payload.len() >= 2 => {
+                self.stats.nacks += 1;
+                if let Some(frame) = self
+                    .pending
+                    .iter_mut()
+                    .find(|frame| frame.seq == payload[1])
+                {
+                    frame.nacked = true;
+                }
+                Ok(None)
+            }
+            _ => Ok(Some(payload.to_vec())),
+        }
+    }
+
+    /// Returns the frames that have not been acknowledged in time and should
+    /// be written again. Frames out of retries are dropped.
+    pub fn due_retransmissions(&mut self) -> Vec<Vec<u8>> {
+        self.due_retransmissions_at(Instant::now())
+    }
+
+    fn due_retransmissions_at(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+
+        self.pending.retain_mut(|frame| {
+            if !frame.nacked && now.duration_since(frame.sent_at) < ACK_TIMEOUT {
+                return true;
+            }
+            if frame.attempts >= MAX_RETRIES {
+                eprintln!(
+                    "Frame {:#04X} was never acknowledged, dropping it",
+                    frame.seq
+                );
+                self.stats.dropped += 1;
+                return false;
+            }
+            frame.attempts += 1;
+            frame.sent_at = now;
+            frame.nacked = false;
+            self.stats.retransmissions += 1;
+            frames.push(frame.frame.clone());
+            true
+        });
+
+        frames
+    }
+
+    /// When [`Link::due_retransmissions`] will have something to resend next,
+    /// `None` while nothing is waiting for an acknowledgement.
+    pub fn next_retransmission(&self) -> Option<Instant> {
+        self.pending
+            .iter()
+            .map(|frame| {
+                if frame.nacked {
+                    frame.sent_at
+                } else {
+                    frame.sent_at + ACK_TIMEOUT
+                }
+            })
+            .min()
+    }
+
+    /// Forgets every frame still waiting for an acknowledgement.
+    pub fn reset(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// Lays out `payload` as described on [`Link`] and COBS encodes it.
+pub fn link_frame(seq: u8, payload: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(payload.len() + 3);
+    data.push(seq);
+    data.extend_from_slice(payload);
+    data.extend_from_slice(&crc16(&data).to_le_bytes());
+    encode_frame(&data)
+}
+
+/// Verifies the checksum of a decoded frame and splits it into its sequence
+/// number and payload, which is never empty.
+pub fn split_link_frame(data: &[u8]) -> Result<(u8, &[u8]), ProtocolError> {
+    if data.len() < 4 {
+        return Err(ProtocolError::Truncated {
+            expected: 4,
+            actual: data.len(),
+        });
+    }
+
+    let (body, crc) = data.split_at(data.len() - 2);
+    let expected = u16::from_le_bytes([crc[0], crc[1]]);
+    let actual = crc16(body);
+    if actual != expected {
+        return Err(ProtocolError::BadChecksum { expected, actual });
+    }
+
+    Ok((body[0], &body[1..]))
+}
+
+/// CRC-16/CCITT-FALSE (poly 0x1021, init 0xFFFF).
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::decode_frame;
+
+    /// Strips the delimiter off a frame and decodes it.
+    fn decoded(frame: &[u8]) -> Vec<u8> {
+        decode_frame(&frame[..frame.len() - 1]).unwrap()
+    }
+
+    /// The frame the device would send to acknowledge or reject `seq`.
+    fn reply(command: CommandIn, seq: u8) -> Vec<u8> {
+        decoded(&link_frame(0, &[command as u8, seq]))
+    }
+
+    #[test]
+    fn crc16_known_answer() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(b""), 0xFFFF);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let frame = decoded(&link_frame(7, &[0x02, 1, 50]));
+        assert_eq!(frame, [7, 0x02, 1, 50, 0xAD, 0x9E]);
+        assert_eq!(split_link_frame(&frame).unwrap(), (7, &[0x02, 1, 50][..]));
+    }
+
+    #[test]
+    fn corrupted_frame_is_rejected() {
+        let mut link = Link::new();
+        let mut frame = decoded(&link_frame(0, &[0x82, 1, 50]));
+        frame[2] ^= 0xFF;
+        assert!(matches!(
+            link.unwrap(&frame),
+            Err(ProtocolError::BadChecksum { .. })
+        ));
+        assert_eq!(link.stats.crc_errors, 1);
+        assert_eq!(link.stats.frames_received, 0);
+    }
+
+    #[test]
+    fn sequence_numbers_wrap() {
+        let mut link = Link::new();
+        let seqs = (0..300)
+            .map(|_| decoded(&link.wrap(&[0x01]))[0])
+            .collect::<Vec<_>>();
+        assert_eq!(seqs[..3], [0, 1, 2]);
+        assert_eq!(seqs[255..258], [255, 0, 1]);
+    }
+
+    #[test]
+    fn ack_clears_only_its_frame() {
+        let mut link = Link::new();
+        link.wrap(&[0x01]);
+        link.wrap(&[0x04, 1]);
+
+        assert_eq!(link.unwrap(&reply(CommandIn::Ack, 0)).unwrap(), None);
+        // A duplicate ack for a frame already cleared changes nothing
+        assert_eq!(link.unwrap(&reply(CommandIn::Ack, 0)).unwrap(), None);
+        assert_eq!(link.stats.acks, 2);
+
+        let later = Instant::now() + ACK_TIMEOUT;
+        let resent = link.due_retransmissions_at(later);
+        assert_eq!(resent.len(), 1);
+        assert_eq!(decoded(&resent[0])[0], 1);
+    }
+
+    #[test]
+    fn other_frames_are_handed_back() {
+        let mut link = Link::new();
+        let frame = decoded(&link_frame(9, &[0x82, 1, 50]));
+        assert_eq!(link.unwrap(&frame).unwrap(), Some(vec![0x82, 1, 50]));
+    }
+
+    #[test]
+    fn nack_resends_immediately() {
+        let mut link = Link::new();
+        let frame = link.wrap(&[0x02, 1, 50]);
+        assert!(link.due_retransmissions().is_empty());
+
+        link.unwrap(&reply(CommandIn::Nack, 0)).unwrap();
+        assert_eq!(link.stats.nacks, 1);
+        assert!(link.next_retransmission().unwrap() <= Instant::now());
+        assert_eq!(link.due_retransmissions(), [frame]);
+        assert_eq!(link.stats.retransmissions, 1);
+
+        // The resent frame waits for its own acknowledgement again
+        assert!(link.due_retransmissions().is_empty());
+        link.unwrap(&reply(CommandIn::Ack, 0)).unwrap();
+        assert_eq!(link.next_retransmission(), None);
+    }
+
+    #[test]
+    fn nack_for_unknown_frame_is_ignored() {
+        let mut link = Link::new();
+        link.wrap(&[0x01]);
+        link.unwrap(&reply(CommandIn::Nack, 5)).unwrap();
+        assert!(link.due_retransmissions().is_empty());
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let mut link = Link::new();
+        let frame = link.wrap(&[0x01]);
+
+        let mut now = Instant::now();
+        for attempt in 1..=MAX_RETRIES {
+            now += ACK_TIMEOUT;
+            assert_eq!(
+                link.due_retransmissions_at(now),
+                std::slice::from_ref(&frame)
+            );
+            assert_eq!(link.stats.retransmissions, attempt as u32);
+        }
+
+        now += ACK_TIMEOUT;
+        assert!(link.due_retransmissions_at(now).is_empty());
+        assert_eq!(link.stats.dropped, 1);
+        assert_eq!(link.next_retransmission(), None);
+    }
+
+    #[test]
+    fn reset_forgets_pending_frames() {
+        let mut link = Link::new();
+        link.wrap(&[0x01]);
+        link.reset();
+        assert!(
+            link.due_retransmissions_at(Instant::now() + ACK_TIMEOUT)
+                .is_empty()
+        );
+    }
+}
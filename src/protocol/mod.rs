@@ -0,0 +1,16 @@
+mod capture;
+pub use capture::*;
+mod codec;
+pub use codec::*;
+mod framing;
+pub use framing::*;
+mod heartbeat;
+pub use heartbeat::*;
+mod io;
+pub use io::*;
+mod link;
+pub use link::*;
+mod transport;
+pub use transport::*;
+mod update;
+pub use update::*;
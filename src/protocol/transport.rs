@@ -0,0 +1,73 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use serialport::SerialPort;
+
+use crate::protocol::READ_TIMEOUT;
+
+/// How long connecting to a network device may take.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Byte stream to a mixer, whatever carries it.
+///
+/// Reads have to time out after about [`READ_TIMEOUT`] so the reader notices
+/// when its session is over, see [`crate::protocol::read_loop`].
+pub trait Transport: Read + Write + Send {
+    /// A second handle to the same stream, the session reads from one and
+    /// writes to the other.
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>>;
+}
+
+impl Transport for Box<dyn SerialPort> {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
+
+impl Transport for TcpStream {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn try_clone_transport(&self) -> io::Result<Box<dyn Transport>> {
+        Ok(Box::new(self.try_clone()?))
+    }
+}
+
+/// Connects to a mixer listening on `address`, e.g. an ESP32 on Wi-Fi.
+pub fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{address} did not resolve to any address"),
+    );
+
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                stream.set_write_timeout(Some(READ_TIMEOUT))?;
+                // Commands are a handful of bytes, don't let Nagle hold them back
+                stream.set_nodelay(true)?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Connects to a mixer listening on a Unix domain socket, e.g. the emulator.
+#[cfg(unix)]
+pub fn connect_unix(path: &std::path::Path) -> io::Result<std::os::unix::net::UnixStream> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    stream.set_write_timeout(Some(READ_TIMEOUT))?;
+    Ok(stream)
+}
@@ -0,0 +1,488 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::protocol::{
+    CommandsIn, CommandsOut, FirmwareVersion, StartUpdateProps, UpdateChunkProps, crc16,
+};
+
+/// Bytes of image per [`CommandsOut::UpdateChunk`], well under a frame.
+pub const UPDATE_CHUNK_LEN: usize = 1024;
+/// How long the device may stay silent during an update before the
+/// transfer is restarted from what it reports holding.
+pub const UPDATE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How many restarts in a row before the update is given up on.
+pub const MAX_UPDATE_STALLS: u32 = 5;
+
+/// Where ESP-IDF puts `esp_app_desc_t`: after the 24 byte image header and
+/// the 8 byte header of the first segment.
+const APP_DESC_OFFSET: usize = 0x20;
+const APP_DESC_MAGIC: u32 = 0xABCD_5432;
+/// The `version` field of the descriptor, NUL padded.
+const APP_DESC_VERSION: std::ops::Range<usize> = 0x30..0x50;
+
+/// Everything that can make a file unusable as a firmware image.
+#[derive(Debug)]
+pub enum ImageError {
+    /// The file is not an ESP32 application image.
+    NoAppDescriptor,
+    BadVersion(String),
+    TooLarge,
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImageError::NoAppDescriptor => write!(f, "not an ESP32 application image"),
+            ImageError::BadVersion(version) => {
+                write!(f, "image version \"{version}\" is not major.minor.patch")
+            }
+            ImageError::TooLarge => write!(f, "image is larger than 4 GiB"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+/// A firmware image and what the device is told about it.
+#[derive(Debug)]
+pub struct FirmwareImage {
+    pub data: Vec<u8>,
+    /// Read from the application descriptor embedded in the image.
+    pub version: FirmwareVersion,
+    /// CRC-32 of `data`.
+    pub checksum: u32,
+}
+
+impl FirmwareImage {
+    pub fn parse(data: Vec<u8>) -> Result<Self, ImageError> {
+        if u32::try_from(data.len()).is_err() {
+            return Err(ImageError::TooLarge);
+        }
+        let magic = data
+            .get(APP_DESC_OFFSET..APP_DESC_OFFSET + 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        let version = data.get(APP_DESC_VERSION);
+        let (Some(APP_DESC_MAGIC), Some(version)) = (magic, version) else {
+            return Err(ImageError::NoAppDescriptor);
+        };
+
+        let version = String::from_utf8_lossy(version);
+        let version = version.trim_end_matches('\0');
+        Ok(Self {
+            version: parse_version(version)
+                .ok_or_else(|| ImageError::BadVersion(version.to_string()))?,
+            checksum: crc32(&data),
+            data,
+        })
+    }
+
+    pub fn size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    /// Whether flashing the image moves a device running `running` forward.
+    /// Reinstalls and downgrades are refused.
+    pub fn is_upgrade_from(&self, running: FirmwareVersion) -> bool {
+        self.version > running
+    }
+
+    /// The chunk starting at `offset`.
+    pub fn chunk(&self, offset: u32) -> UpdateChunkProps {
+        let start = (offset as usize).min(self.data.len());
+        let end = (start + UPDATE_CHUNK_LEN).min(self.data.len());
+        let data = self.data[start..end].to_vec();
+        UpdateChunkProps {
+            offset,
+            crc: crc16(&data),
+            data,
+        }
+    }
+}
+
+/// Reads `1.2.3`, `v1.2.3` or a `git describe` style `v1.2.3-4-gabcdef`.
+fn parse_version(version: &str) -> Option<FirmwareVersion> {
+    let version = version.strip_prefix('v').unwrap_or(version);
+    let mut parts = version.splitn(3, '.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = parts.next()?;
+    let digits = patch
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(patch.len());
+    Some(FirmwareVersion {
+        major,
+        minor,
+        patch: patch[..digits].parse().ok()?,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UploadState {
+    /// Waiting for the device to report how much it already holds.
+    Starting,
+    Sending,
+    /// Everything was sent, waiting for the device to check the image.
+    Finishing,
+    Done,
+    Failed(String),
+}
+
+impl UploadState {
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            UploadState::Starting | UploadState::Sending | UploadState::Finishing
+        )
+    }
+}
+
+/// Host side of a firmware update. Sends one chunk at a time, each at the
+/// offset the device asks for, so lost or corrupted chunks are simply sent
+/// again and a transfer cut short by a disconnect resumes where it stopped.
+pub struct Uploader {
+    image: Arc<FirmwareImage>,
+    /// Bytes the device confirmed holding.
+    confirmed: u32,
+    state: UploadState,
+    last_reply: Instant,
+    stalls: u32,
+}
+
+impl Uploader {
+    /// Returns the uploader and the command that starts the transfer.
+    pub fn new(image: Arc<FirmwareImage>) -> (Self, CommandsOut) {
+        let uploader = Self {
+            image,
+            confirmed: 0,
+            state: UploadState::Starting,
+            last_reply: Instant::now(),
+            stalls: 0,
+        };
+        let start = uploader.start_command();
+        (uploader, start)
+    }
+
+    pub fn image(&self) -> &FirmwareImage {
+        &self.image
+    }
+
+    pub fn state(&self) -> &UploadState {
+        &self.state
+    }
+
+    /// Share of the image the device holds, from 0 to 1.
+    pub fn progress(&self) -> f64 {
+        match self.image.size() {
+            0 => 1.0,
+            size => self.confirmed as f64 / size as f64,
+        }
+    }
+
+    /// Feeds a reply from the device, returning what to send next.
+    pub fn handle(&mut self, reply: &CommandsIn) -> Option<CommandsOut> {
+        if !self.state.is_active() {
+            return None;
+        }
+
+        match *reply {
+            CommandsIn::UpdateProgress(received) => {
+                self.last_reply = Instant::now();
+                self.stalls = 0;
+                if received > self.image.size() {
+                    self.state = UploadState::Failed(format!(
+                        "device holds {} bytes of a {} byte image",
+                        received,
+                        self.image.size()
+                    ));
+                    return None;
+                }
+
+                self.confirmed = received;
+                if received == self.image.size() {
+                    self.state = UploadState::Finishing;
+                    Some(CommandsOut::FinishUpdate)
+                } else {
+                    self.state = UploadState::Sending;
+                    Some(CommandsOut::UpdateChunk(self.image.chunk(received)))
+                }
+            }
+            CommandsIn::UpdateFinished(true) => {
+                self.state = UploadState::Done;
+                None
+            }
+            CommandsIn::UpdateFinished(false) => {
+                self.state = UploadState::Failed("the device rejected the image".to_string());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the device has been silent for [`UPDATE_TIMEOUT`] in the
+    /// middle of the transfer.
+    pub fn is_stalled(&self, now: Instant) -> bool {
+        self.state.is_active() && now.duration_since(self.last_reply) >= UPDATE_TIMEOUT
+    }
+
+    /// Picks the transfer up again once it stalled, e.g. after a reconnect.
+    pub fn poll_stall(&mut self, now: Instant) -> Option<CommandsOut> {
+        if !self.is_stalled(now) {
+            return None;
+        }
+
+        self.stalls += 1;
+        if self.stalls > MAX_UPDATE_STALLS {
+            self.state = UploadState::Failed("the device stopped answering".to_string());
+            return None;
+        }
+        self.last_reply = now;
+        match self.state {
+            // Everything was sent, only the verdict went missing
+            UploadState::Finishing => Some(CommandsOut::FinishUpdate),
+            _ => Some(self.start_command()),
+        }
+    }
+
+    fn start_command(&self) -> CommandsOut {
+        CommandsOut::StartUpdate(StartUpdateProps {
+            size: self.image.size(),
+            checksum: self.image.checksum,
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u8, minor: u8, patch: u8) -> FirmwareVersion {
+        FirmwareVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// An image of `len` bytes carrying an application descriptor.
+    fn image_data(version: &str, len: usize) -> Vec<u8> {
+        let mut data = (0..len).map(|i| i as u8).collect::<Vec<_>>();
+        data[APP_DESC_OFFSET..APP_DESC_OFFSET + 4].copy_from_slice(&APP_DESC_MAGIC.to_le_bytes());
+        data[APP_DESC_VERSION].fill(0);
+        data[APP_DESC_VERSION.start..APP_DESC_VERSION.start + version.len()]
+            .copy_from_slice(version.as_bytes());
+        data
+    }
+
+    fn uploader(len: usize) -> (Uploader, CommandsOut) {
+        let image = FirmwareImage::parse(image_data("1.0.0", len)).unwrap();
+        Uploader::new(Arc::new(image))
+    }
+
+    fn chunk_at(command: Option<CommandsOut>) -> (u32, usize) {
+        match command {
+            Some(CommandsOut::UpdateChunk(chunk)) => {
+                assert_eq!(chunk.crc, crc16(&chunk.data));
+                (chunk.offset, chunk.data.len())
+            }
+            other => panic!("expected a chunk, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn crc32_known_answer() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn parses_the_app_descriptor() {
+        let data = image_data("v1.2.3-4-gabcdef", 0x100);
+        let checksum = crc32(&data);
+        let image = FirmwareImage::parse(data).unwrap();
+        assert_eq!(image.version, version(1, 2, 3));
+        assert_eq!(image.checksum, checksum);
+        assert_eq!(image.size(), 0x100);
+    }
+
+    #[test]
+    fn rejects_images_without_descriptor() {
+        let mut data = image_data("1.2.3", 0x100);
+        data[APP_DESC_OFFSET] ^= 0xFF;
+        assert!(matches!(
+            FirmwareImage::parse(data),
+            Err(ImageError::NoAppDescriptor)
+        ));
+
+        // Cut off in the middle of the version field
+        let data = image_data("1.2.3", 0x100)[..0x40].to_vec();
+        assert!(matches!(
+            FirmwareImage::parse(data),
+            Err(ImageError::NoAppDescriptor)
+        ));
+        assert!(matches!(
+            FirmwareImage::parse(Vec::new()),
+            Err(ImageError::NoAppDescriptor)
+        ));
+    }
+
+    #[test]
+    fn rejects_unreadable_versions() {
+        for version in ["", "1.2", "one.two.three", "1.2.x", "300.0.0"] {
+            assert!(
+                matches!(
+                    FirmwareImage::parse(image_data(version, 0x100)),
+                    Err(ImageError::BadVersion(_))
+                ),
+                "{version:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_upgrades_are_accepted() {
+        let image = FirmwareImage::parse(image_data("1.2.3", 0x100)).unwrap();
+        assert!(image.is_upgrade_from(version(1, 2, 2)));
+        assert!(image.is_upgrade_from(version(0, 9, 9)));
+        assert!(!image.is_upgrade_from(version(1, 2, 3)));
+        assert!(!image.is_upgrade_from(version(1, 3, 0)));
+        assert!(!image.is_upgrade_from(version(2, 0, 0)));
+    }
+
+    #[test]
+    fn sends_chunks_at_the_requested_offsets() {
+        let len = UPDATE_CHUNK_LEN * 2 + 512;
+        let (mut uploader, start) = uploader(len);
+        let checksum = uploader.image().checksum;
+        assert_eq!(
+            start,
+            CommandsOut::StartUpdate(StartUpdateProps {
+                size: len as u32,
+                checksum,
+            })
+        );
+        assert_eq!(*uploader.state(), UploadState::Starting);
+
+        assert_eq!(
+            chunk_at(uploader.handle(&CommandsIn::UpdateProgress(0))),
+            (0, UPDATE_CHUNK_LEN)
+        );
+        assert_eq!(*uploader.state(), UploadState::Sending);
+        assert_eq!(
+            chunk_at(uploader.handle(&CommandsIn::UpdateProgress(1024))),
+            (1024, UPDATE_CHUNK_LEN)
+        );
+        // The device asks for a chunk again when it arrived corrupted
+        assert_eq!(
+            chunk_at(uploader.handle(&CommandsIn::UpdateProgress(1024))),
+            (1024, UPDATE_CHUNK_LEN)
+        );
+        assert_eq!(
+            chunk_at(uploader.handle(&CommandsIn::UpdateProgress(2048))),
+            (2048, 512)
+        );
+
+        assert_eq!(
+            uploader.handle(&CommandsIn::UpdateProgress(len as u32)),
+            Some(CommandsOut::FinishUpdate)
+        );
+        assert_eq!(*uploader.state(), UploadState::Finishing);
+        assert_eq!(uploader.progress(), 1.0);
+
+        assert_eq!(uploader.handle(&CommandsIn::UpdateFinished(true)), None);
+        assert_eq!(*uploader.state(), UploadState::Done);
+        // Nothing is sent once the update is over
+        assert_eq!(uploader.handle(&CommandsIn::UpdateProgress(0)), None);
+    }
+
+    #[test]
+    fn fails_on_impossible_progress() {
+        let (mut uploader, _) = uploader(0x100);
+        assert_eq!(uploader.handle(&CommandsIn::UpdateProgress(0x101)), None);
+        assert!(matches!(uploader.state(), UploadState::Failed(_)));
+    }
+
+    #[test]
+    fn fails_when_the_device_rejects_the_image() {
+        let (mut uploader, _) = uploader(0x100);
+        uploader.handle(&CommandsIn::UpdateProgress(0x100));
+        assert_eq!(uploader.handle(&CommandsIn::UpdateFinished(false)), None);
+        assert!(matches!(uploader.state(), UploadState::Failed(_)));
+    }
+
+    #[test]
+    fn resumes_after_a_stall() {
+        let (mut uploader, start) = uploader(UPDATE_CHUNK_LEN * 4);
+        uploader.handle(&CommandsIn::UpdateProgress(0));
+        uploader.handle(&CommandsIn::UpdateProgress(2048));
+
+        let now = Instant::now();
+        assert!(!uploader.is_stalled(now));
+        assert_eq!(uploader.poll_stall(now), None);
+
+        // Silence restarts the handshake, the device answers with what it holds
+        let later = now + UPDATE_TIMEOUT;
+        assert!(uploader.is_stalled(later));
+        assert_eq!(uploader.poll_stall(later), Some(start));
+        assert!(!uploader.is_stalled(later));
+        assert_eq!(
+            chunk_at(uploader.handle(&CommandsIn::UpdateProgress(2048))),
+            (2048, UPDATE_CHUNK_LEN)
+        );
+    }
+
+    #[test]
+    fn asks_for_the_verdict_again_after_a_stall() {
+        let (mut uploader, _) = uploader(0x100);
+        uploader.handle(&CommandsIn::UpdateProgress(0x100));
+        assert_eq!(
+            uploader.poll_stall(Instant::now() + UPDATE_TIMEOUT),
+            Some(CommandsOut::FinishUpdate)
+        );
+    }
+
+    #[test]
+    fn gives_up_after_max_stalls() {
+        let (mut uploader, start) = uploader(0x100);
+        let mut now = Instant::now();
+        for _ in 0..MAX_UPDATE_STALLS {
+            now += UPDATE_TIMEOUT;
+            assert_eq!(uploader.poll_stall(now), Some(start.clone()));
+        }
+
+        now += UPDATE_TIMEOUT;
+        assert_eq!(uploader.poll_stall(now), None);
+        assert!(matches!(uploader.state(), UploadState::Failed(_)));
+        assert!(!uploader.is_stalled(now + UPDATE_TIMEOUT));
+    }
+
+    #[test]
+    fn a_reply_resets_the_stall_count() {
+        let (mut uploader, _) = uploader(0x100);
+        let mut now = Instant::now();
+        for _ in 0..MAX_UPDATE_STALLS * 2 {
+            now += UPDATE_TIMEOUT;
+            assert!(uploader.poll_stall(now).is_some());
+            uploader.handle(&CommandsIn::UpdateProgress(0));
+            now = Instant::now();
+        }
+        assert_eq!(*uploader.state(), UploadState::Sending);
+    }
+}
@@ -0,0 +1,682 @@
+use std::{
+    io::Read,
+    process::{Command, Output, Stdio},
+    sync::{
+        Mutex, OnceLock,
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+/// How often the running applications, sinks and sources are listed.
+pub const TARGET_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// `pactl` volume at 100%.
+const VOLUME_NORM: f64 = 65536.0;
+/// Samples per second recorded by a meter, peaks only need a coarse signal.
+const METER_RATE: u32 = 1000;
+/// Samples one peak is taken over, about a feedback tick's worth.
+const METER_CHUNK: usize = 50;
+/// A meter nobody asked for a peak for this long is stopped.
+const METER_IDLE: Duration = Duration::from_secs(1);
+/// How long a meter whose recording failed waits before starting over.
+const METER_RETRY: Duration = Duration::from_secs(5);
+
+/// What a slider controls.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VolumeAction {
+    /// Only prints the volume, for sliders without a target.
+    Print,
+    /// The default output.
+    Master,
+    /// Every stream of an application, matched on its name or binary.
+    App(String),
+    /// An output by name, `default` for the default one.
+    Sink(String),
+    /// An input by name, `default` for the default one.
+    Source(String),
+    /// Shell command run with `VOLUME` (percent) and `MUTED` (0 or 1) set.
+    Command(String),
+}
+
+impl VolumeAction {
+    /// Parses a target: `master`, `app:<name>`, `sink:<name>`,
+    /// `source:<name>` or `cmd:<command>`. An empty string leaves the slider
+    /// unbound.
+    ///
+    /// This only checks the syntax, use [`VolumeAction::from_device`] or
+    /// [`VolumeAction::from_user`] depending on where the target comes from.
+    pub fn parse(action: &str) -> Result<Self, TargetError> {
+        let action = action.trim();
+        let argument = |kind: &'static str, argument: &str| match argument.trim() {
+            "" => Err(TargetError::MissingArgument(kind)),
+            argument => Ok(argument.to_string()),
+        };
+        match action.split_once(':') {
+            Some(("app", name)) => argument("app", name).map(VolumeAction::App),
+            Some(("sink", name)) => argument("sink", name).map(VolumeAction::Sink),
+            Some(("source", name)) => argument("source", name).map(VolumeAction::Source),
+            Some(("cmd", command)) => argument("cmd", command).map(VolumeAction::Command),
+            _ => match action {
+                "" => Ok(VolumeAction::Print),
+                "master" => Ok(VolumeAction::Master),
+                _ => Err(TargetError::UnknownTarget(action.to_string())),
+            },
+        }
+    }
+
+    /// Parses a target declared by the device. Commands are refused, anything
+    /// on the other end of the port could declare them.
+    pub fn from_device(action: &str) -> Result<Self, TargetError> {
+        match Self::parse(action)? {
+            VolumeAction::Command(_) => Err(TargetError::CommandFromDevice),
+            action => Ok(action),
+        }
+    }
+
+    /// Parses a target entered on this machine. Commands are only accepted
+    /// when the config opts in with `allow_commands`.
+    pub fn from_user(action: &str, allow_commands: bool) -> Result<Self, TargetError> {
+        match Self::parse(action)? {
+            VolumeAction::Command(_) if !allow_commands => Err(TargetError::CommandsDisabled),
+            action => Ok(action),
+        }
+    }
+}
+
+/// Why a slider target could not be parsed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TargetError {
+    UnknownTarget(String),
+    /// The kind of target, given without a name or command.
+    MissingArgument(&'static str),
+    /// A `cmd:` target declared by the device.
+    CommandFromDevice,
+    /// A `cmd:` target without `allow_commands` in the config.
+    CommandsDisabled,
+}
+
+impl std::fmt::Display for TargetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TargetError::UnknownTarget(target) => write!(
+                f,
+                "unknown target \"{target}\", expected master, app:<name>, sink:<name>, \
+                 source:<name> or cmd:<command>"
+            ),
+            TargetError::MissingArgument("cmd") => write!(f, "\"cmd:\" needs a command"),
+            TargetError::MissingArgument(kind) => write!(f, "\"{kind}:\" needs a name"),
+            TargetError::CommandFromDevice => {
+                write!(f, "commands can only be bound from the settings page")
+            }
+            TargetError::CommandsDisabled => write!(
+                f,
+                "commands are disabled, set \"allow_commands\": true in the config to bind them"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TargetError {}
+
+struct VolumeRequest {
+    action: VolumeAction,
+    level: f64,
+    muted: bool,
+}
+
+/// Sets the volume of `action` to `level` (0 to 1) in the background.
+///
+/// A single worker applies the requests. It only keeps the latest one per
+/// target, so a fader sweep doesn't start a `pactl` process for every frame.
+pub fn set_volume(action: &VolumeAction, level: f64, muted: bool) {
+    static REQUESTS: OnceLock<Sender<VolumeRequest>> = OnceLock::new();
+    let requests = REQUESTS.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<VolumeRequest>();
+        thread::spawn(move || {
+            while let Ok(request) = rx.recv() {
+                let mut pending = vec![request];
+                for request in rx.try_iter() {
+                    pending.retain(|pending| pending.action != request.action);
+                    pending.push(request);
+                }
+                for request in pending {
+                    if let Err(e) = apply(&request) {
+                        eprintln!("Failed to set volume of {:?}: {}", request.action, e);
+                    }
+                }
+            }
+        });
+        tx
+    });
+
+    let _ = requests.send(VolumeRequest {
+        action: action.clone(),
+        level,
+        muted,
+    });
+}
+
+fn apply(request: &VolumeRequest) -> Result<(), String> {
+    let volume = ((request.level * VOLUME_NORM).round() as u32).to_string();
+    let muted = if request.muted { "1" } else { "0" };
+    match &request.action {
+        VolumeAction::Print => Ok(()),
+        VolumeAction::Master => set_device("sink", "@DEFAULT_SINK@", &volume, muted),
+        VolumeAction::Sink(name) => {
+            set_device("sink", default_or(name, "@DEFAULT_SINK@"), &volume, muted)
+        }
+        VolumeAction::Source(name) => set_device(
+            "source",
+            default_or(name, "@DEFAULT_SOURCE@"),
+            &volume,
+            muted,
+        ),
+        VolumeAction::App(name) => {
+            let streams = list_streams()?;
+            let indices = streams
+                .iter()
+                .filter(|stream| stream.belongs_to(name))
+                .map(|stream| stream.index.to_string());
+            for index in indices {
+                pactl(&["set-sink-input-volume", &index, &volume])?;
+                pactl(&["set-sink-input-mute", &index, muted])?;
+            }
+            Ok(())
+        }
+        VolumeAction::Command(command) => {
+            let output = Command::new("sh")
+                .args(["-c", command])
+                .env("VOLUME", format!("{:.2}", request.level * 100.0))
+                .env("MUTED", muted)
+                .output()
+                .map_err(|e| e.to_string())?;
+            check(output)
+        }
+    }
+}
+
+fn set_device(kind: &str, name: &str, volume: &str, muted: &str) -> Result<(), String> {
+    pactl(&[&format!("set-{kind}-volume"), name, volume])?;
+    pactl(&[&format!("set-{kind}-mute"), name, muted])?;
+    Ok(())
+}
+
+fn default_or<'a>(name: &'a str, default: &'a str) -> &'a str {
+    if name == "default" { default } else { name }
+}
+
+fn pactl(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("pactl")
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to run pactl: {e}"))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    check(output).map(|_| stdout)
+}
+
+fn check(output: Output) -> Result<(), String> {
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// A playback stream, as listed by `pactl -f json list sink-inputs`.
+#[derive(Deserialize)]
+struct Stream {
+    index: u32,
+    properties: StreamProperties,
+}
+
+#[derive(Deserialize)]
+struct StreamProperties {
+    #[serde(rename = "application.name")]
+    name: Option<String>,
+    #[serde(rename = "application.process.binary")]
+    binary: Option<String>,
+}
+
+impl Stream {
+    fn belongs_to(&self, app: &str) -> bool {
+        [&self.properties.name, &self.properties.binary]
+            .into_iter()
+            .flatten()
+            .any(|name| name.eq_ignore_ascii_case(app))
+    }
+}
+
+fn list_streams() -> Result<Vec<Stream>, String> {
+    let json = pactl(&["-f", "json", "list", "sink-inputs"])?;
+    serde_json::from_str(&json).map_err(|e| format!("unexpected pactl output: {e}"))
+}
+
+/// Names from the second column of `pactl list short sinks` or `sources`.
+fn list_devices(kind: &str) -> Result<Vec<String>, String> {
+    let list = pactl(&["list", "short", kind])?;
+    Ok(list
+        .lines()
+        .filter_map(|line| line.split('\t').nth(1))
+        .map(str::to_string)
+        .collect())
+}
+
+/// What was running at the last poll.
+struct Targets {
+    streams: Vec<Stream>,
+    sinks: Vec<String>,
+    sources: Vec<String>,
+}
+
+fn poll_targets() -> Result<Targets, String> {
+    Ok(Targets {
+        streams: list_streams()?,
+        sinks: list_devices("sinks")?,
+        sources: list_devices("sources")?,
+    })
+}
+
+/// What the background poller last saw, and whether anybody still asks.
+struct Poller {
+    targets: Targets,
+    running: bool,
+    last_wanted: Option<Instant>,
+}
+
+static POLLER: Mutex<Poller> = Mutex::new(Poller {
+    targets: Targets {
+        streams: Vec::new(),
+        sinks: Vec::new(),
+        sources: Vec::new(),
+    },
+    running: false,
+    last_wanted: None,
+});
+
+/// Whether whatever the slider controls is there to be controlled, e.g. the
+/// bound application is running.
+///
+/// This is asked for every slider on each feedback tick, so it answers from
+/// a list refreshed every [`TARGET_POLL_INTERVAL`] by a background thread.
+/// The thread is started by the first target that needs the list and stops
+/// once none has asked for a whole interval.
+pub fn target_running(action: &VolumeAction) -> bool {
+    match action {
+        VolumeAction::Print | VolumeAction::Master | VolumeAction::Command(_) => return true,
+        VolumeAction::Sink(name) | VolumeAction::Source(name) if name == "default" => {
+            return true;
+        }
+        _ => {}
+    }
+
+    let mut poller = POLLER.lock().unwrap();
+    poller.last_wanted = Some(Instant::now());
+    if !poller.running {
+        poller.running = true;
+        thread::spawn(poll_targets_while_wanted);
+    }
+    let targets = &poller.targets;
+    match action {
+        VolumeAction::App(name) => targets.streams.iter().any(|stream| stream.belongs_to(name)),
+        VolumeAction::Sink(name) => targets.sinks.contains(name),
+        VolumeAction::Source(name) => targets.sources.contains(name),
+        _ => true,
+    }
+}
+
+fn poll_targets_while_wanted() {
+    let mut failing = false;
+    loop {
+        match poll_targets() {
+            Ok(targets) => {
+                POLLER.lock().unwrap().targets = targets;
+                failing = false;
+            }
+            // Only report the first of a streak, pactl may be missing entirely
+            Err(e) if !failing => {
+                eprintln!("Failed to list audio targets: {}", e);
+                failing = true;
+            }
+            Err(_) => {}
+        }
+        thread::sleep(TARGET_POLL_INTERVAL);
+
+        let mut poller = POLLER.lock().unwrap();
+        let wanted = poller
+            .last_wanted
+            .is_some_and(|at| at.elapsed() < TARGET_POLL_INTERVAL);
+        if !wanted {
+            // Whatever runs by the next start may have changed completely
+            poller.targets.streams.clear();
+            poller.targets.sinks.clear();
+            poller.targets.sources.clear();
+            poller.running = false;
+            return;
+        }
+    }
+}
+
+/// Level of one target's audio, recorded by its own `parec` process.
+struct Meter {
+    action: VolumeAction,
+    /// Highest absolute sample of the last chunk, from 0 to 1.
+    peak: f32,
+    last_wanted: Instant,
+    /// Set while waiting to restart a recording that failed.
+    failed_at: Option<Instant>,
+    /// Recordings that failed in a row, only the first one is reported.
+    failures: u32,
+}
+
+static METERS: Mutex<Vec<Meter>> = Mutex::new(Vec::new());
+
+/// Peak level of the audio going through the slider's target right now,
+/// from 0 to 1.
+///
+/// Asked for on every feedback tick, so the first call for a target starts
+/// recording it in the background and answers 0 until the first chunk is
+/// in. The recording stops once nobody asked for [`METER_IDLE`]. Targets
+/// without audio of their own, printing or commands, are always at 0.
+pub fn peak_level(action: &VolumeAction) -> f32 {
+    if matches!(action, VolumeAction::Print | VolumeAction::Command(_)) {
+        return 0.0;
+    }
+
+    let now = Instant::now();
+    let mut meters = METERS.lock().unwrap();
+    let Some(meter) = meters.iter_mut().find(|meter| meter.action == *action) else {
+        meters.push(Meter {
+            action: action.clone(),
+            peak: 0.0,
+            last_wanted: now,
+            failed_at: None,
+            failures: 0,
+        });
+        spawn_meter(action.clone());
+        return 0.0;
+    };
+
+    meter.last_wanted = now;
+    if meter
+        .failed_at
+        .is_some_and(|at| now.duration_since(at) >= METER_RETRY)
+    {
+        meter.failed_at = None;
+        spawn_meter(action.clone());
+    }
+    meter.peak
+}
+
+fn spawn_meter(action: VolumeAction) {
+    thread::spawn(move || {
+        let result = record_peaks(&action);
+        let mut meters = METERS.lock().unwrap();
+        let Some(index) = meters.iter().position(|meter| meter.action == action) else {
+            return;
+        };
+        match result {
+            // Nobody is interested anymore
+            Ok(()) => {
+                meters.remove(index);
+            }
+            Err(e) => {
+                let meter = &mut meters[index];
+                if meter.failures == 0 {
+                    eprintln!("Failed to meter {:?}: {}", action, e);
+                }
+                meter.failures += 1;
+                meter.failed_at = Some(Instant::now());
+                meter.peak = 0.0;
+            }
+        }
+    });
+}
+
+/// Stores the peak of every chunk `parec` records until the meter goes
+/// idle, which ends with `Ok`.
+fn record_peaks(action: &VolumeAction) -> Result<(), String> {
+    let mut child = Command::new("parec")
+        .args(meter_args(action, list_streams)?)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to run parec: {e}"))?;
+    let mut stdout = child.stdout.take().ok_or("parec has no output")?;
+
+    let mut chunk = [0; METER_CHUNK * 4];
+    let result = loop {
+        // Ends when the target goes away, e.g. the app closes its stream
+        if let Err(e) = stdout.read_exact(&mut chunk) {
+            break Err(format!("recording ended: {e}"));
+        }
+        let peak = chunk_peak(&chunk);
+
+        let mut meters = METERS.lock().unwrap();
+        let Some(meter) = meters.iter_mut().find(|meter| meter.action == *action) else {
+            break Ok(());
+        };
+        if meter.last_wanted.elapsed() >= METER_IDLE {
+            break Ok(());
+        }
+        meter.peak = peak;
+        meter.failures = 0;
+    };
+
+    let _ = child.kill();
+    let _ = child.wait();
+    result
+}
+
+/// `parec` arguments recording `action` as mono 32-bit floats. Applications
+/// are recorded through the first of their streams, found with `streams`.
+fn meter_args(
+    action: &VolumeAction,
+    streams: impl FnOnce() -> Result<Vec<Stream>, String>,
+) -> Result<Vec<String>, String> {
+    let target = match action {
+        VolumeAction::Master => "--device=@DEFAULT_MONITOR@".to_string(),
+        VolumeAction::Sink(name) if name == "default" => "--device=@DEFAULT_MONITOR@".to_string(),
+        VolumeAction::Sink(name) => format!("--device={name}.monitor"),
+        VolumeAction::Source(name) => {
+            format!("--device={}", default_or(name, "@DEFAULT_SOURCE@"))
+        }
+        VolumeAction::App(name) => {
+            let stream = streams()?
+                .into_iter()
+                .find(|stream| stream.belongs_to(name))
+                .ok_or_else(|| format!("{name} is not playing anything"))?;
+            format!("--monitor-stream={}", stream.index)
+        }
+        VolumeAction::Print | VolumeAction::Command(_) => {
+            return Err("nothing to meter".to_string());
+        }
+    };
+    Ok(vec![
+        target,
+        "--format=float32le".to_string(),
+        "--channels=1".to_string(),
+        format!("--rate={METER_RATE}"),
+        format!("--latency-msec={}", METER_CHUNK as u32 * 1000 / METER_RATE),
+    ])
+}
+
+/// Highest absolute value of the little endian floats in `chunk`, clipped
+/// to 1.
+fn chunk_peak(chunk: &[u8]) -> f32 {
+    chunk
+        .chunks_exact(4)
+        .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]).abs())
+        .filter(|sample| !sample.is_nan())
+        .fold(0.0, f32::max)
+        .min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_target() {
+        let cases = [
+            ("", VolumeAction::Print),
+            ("  ", VolumeAction::Print),
+            ("master", VolumeAction::Master),
+            (" master ", VolumeAction::Master),
+            ("app:Firefox", VolumeAction::App("Firefox".to_string())),
+            ("app: spotify ", VolumeAction::App("spotify".to_string())),
+            ("sink:default", VolumeAction::Sink("default".to_string())),
+            (
+                "sink:alsa_output.usb-headset",
+                VolumeAction::Sink("alsa_output.usb-headset".to_string()),
+            ),
+            (
+                "source:default",
+                VolumeAction::Source("default".to_string()),
+            ),
+            (
+                "cmd:notify-send \"$VOLUME\"",
+                VolumeAction::Command("notify-send \"$VOLUME\"".to_string()),
+            ),
+            // Only the first colon separates the kind
+            ("app:a:b", VolumeAction::App("a:b".to_string())),
+        ];
+        for (action, expected) in cases {
+            assert_eq!(VolumeAction::parse(action), Ok(expected), "{action:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_targets() {
+        let cases = [
+            ("volume", TargetError::UnknownTarget("volume".to_string())),
+            ("Master", TargetError::UnknownTarget("Master".to_string())),
+            (
+                "speaker:left",
+                TargetError::UnknownTarget("speaker:left".to_string()),
+            ),
+            ("app:", TargetError::MissingArgument("app")),
+            ("sink: ", TargetError::MissingArgument("sink")),
+            ("source:", TargetError::MissingArgument("source")),
+            ("cmd:", TargetError::MissingArgument("cmd")),
+        ];
+        for (action, expected) in cases {
+            assert_eq!(VolumeAction::parse(action), Err(expected), "{action:?}");
+        }
+    }
+
+    #[test]
+    fn device_cannot_declare_commands() {
+        assert_eq!(
+            VolumeAction::from_device("cmd:rm -rf ~"),
+            Err(TargetError::CommandFromDevice)
+        );
+        assert_eq!(
+            VolumeAction::from_device("app:firefox"),
+            Ok(VolumeAction::App("firefox".to_string()))
+        );
+        assert_eq!(
+            VolumeAction::from_device("cmd:"),
+            Err(TargetError::MissingArgument("cmd"))
+        );
+    }
+
+    #[test]
+    fn user_commands_need_the_opt_in() {
+        assert_eq!(
+            VolumeAction::from_user("cmd:true", false),
+            Err(TargetError::CommandsDisabled)
+        );
+        assert_eq!(
+            VolumeAction::from_user("cmd:true", true),
+            Ok(VolumeAction::Command("true".to_string()))
+        );
+        assert_eq!(
+            VolumeAction::from_user("master", false),
+            Ok(VolumeAction::Master)
+        );
+    }
+
+    #[test]
+    fn targets_without_a_list_do_not_poll() {
+        for action in [
+            VolumeAction::Print,
+            VolumeAction::Master,
+            VolumeAction::Sink("default".to_string()),
+            VolumeAction::Source("default".to_string()),
+            VolumeAction::Command("true".to_string()),
+        ] {
+            assert!(target_running(&action), "{action:?}");
+        }
+        assert!(!POLLER.lock().unwrap().running);
+    }
+
+    #[test]
+    fn peaks_are_the_loudest_sample() {
+        let chunk = [0.25f32, -0.75, 0.5, f32::NAN]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(chunk_peak(&chunk), 0.75);
+        assert_eq!(chunk_peak(&1.5f32.to_le_bytes()), 1.0);
+        assert_eq!(chunk_peak(&[]), 0.0);
+    }
+
+    #[test]
+    fn meters_record_the_target() {
+        let no_streams = || Ok(Vec::new());
+        let device =
+            |action: VolumeAction| meter_args(&action, no_streams).map(|args| args[0].clone());
+        assert_eq!(
+            device(VolumeAction::Master),
+            Ok("--device=@DEFAULT_MONITOR@".to_string())
+        );
+        assert_eq!(
+            device(VolumeAction::Sink("default".to_string())),
+            Ok("--device=@DEFAULT_MONITOR@".to_string())
+        );
+        assert_eq!(
+            device(VolumeAction::Sink("headset".to_string())),
+            Ok("--device=headset.monitor".to_string())
+        );
+        assert_eq!(
+            device(VolumeAction::Source("default".to_string())),
+            Ok("--device=@DEFAULT_SOURCE@".to_string())
+        );
+        assert_eq!(
+            device(VolumeAction::Source("usb-mic".to_string())),
+            Ok("--device=usb-mic".to_string())
+        );
+        assert!(device(VolumeAction::App("spotify".to_string())).is_err());
+        assert!(device(VolumeAction::Print).is_err());
+
+        let streams = || {
+            serde_json::from_str::<Vec<Stream>>(
+                r#"[
+                    {"index": 7, "properties": {"application.name": "Firefox"}},
+                    {"index": 12, "properties": {"application.process.binary": "spotify"}}
+                ]"#,
+            )
+            .map_err(|e| e.to_string())
+        };
+        assert_eq!(
+            meter_args(&VolumeAction::App("Spotify".to_string()), streams)
+                .map(|args| args[0].clone()),
+            Ok("--monitor-stream=12".to_string())
+        );
+    }
+
+    #[test]
+    fn targets_without_audio_are_not_metered() {
+        assert_eq!(peak_level(&VolumeAction::Print), 0.0);
+        assert_eq!(peak_level(&VolumeAction::Command("true".to_string())), 0.0);
+        assert!(METERS.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+/// Environment variable pointing to an alternative config file.
+pub const CONFIG_ENV: &str = "AUDIOMIXER_CONFIG";
+/// Environment variable forcing a port path, takes precedence over the file.
+pub const PORT_ENV: &str = "AUDIOMIXER_PORT";
+/// Environment variable naming a file to record the traffic to.
+pub const CAPTURE_ENV: &str = "AUDIOMIXER_CAPTURE";
+/// Environment variable naming a capture to play back instead of talking to a
+/// device.
+pub const REPLAY_ENV: &str = "AUDIOMIXER_REPLAY";
+
+/// Describes which USB serial ports belong to a mixer.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct DeviceMatcher {
+    pub vid: u16,
+    pub pid: u16,
+    /// Substring the USB product string has to contain.
+    #[serde(default)]
+    pub product: Option<String>,
+    /// Exact USB serial number.
+    #[serde(default)]
+    pub serial_number: Option<String>,
+}
+
+impl std::fmt::Display for DeviceMatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)?;
+        if let Some(product) = &self.product {
+            write!(f, " product \"{}\"", product)?;
+        }
+        if let Some(serial_number) = &self.serial_number {
+            write!(f, " serial {}", serial_number)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the app reaches the mixer, e.g. `{"type": "tcp", "address": "mixer.local:5000"}`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum TransportConfig {
+    /// USB serial ports picked by [`Config::matchers`] or [`Config::port`].
+    #[default]
+    Serial,
+    Tcp {
+        address: String,
+    },
+    Unix {
+        path: PathBuf,
+    },
+    /// Plays back a file recorded with [`Config::capture`].
+    Replay {
+        path: PathBuf,
+    },
+}
+
+impl std::fmt::Display for TransportConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportConfig::Serial => write!(f, "serial"),
+            TransportConfig::Tcp { address } => write!(f, "tcp://{address}"),
+            TransportConfig::Unix { path } => write!(f, "unix://{}", path.display()),
+            TransportConfig::Replay { path } => write!(f, "replay://{}", path.display()),
+        }
+    }
+}
+
+/// Slider settings applied together by a button bound to `profile:<n>`,
+/// e.g. `{"name": "Call", "sliders": [{"volume": 30}, {"target": "app:zoom", "muted": false}]}`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    /// Starting with slider 1, sliders past the end are left alone.
+    pub sliders: Vec<ProfileSlider>,
+}
+
+/// What a profile changes on one slider, anything missing stays as it is.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProfileSlider {
+    /// Whole percent.
+    pub volume: Option<u8>,
+    pub muted: Option<bool>,
+    /// Same syntax as the slider targets on the settings page.
+    pub target: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Every port matching one of these is treated as a mixer.
+    pub matchers: Vec<DeviceMatcher>,
+    /// Skips matching altogether and always opens this port.
+    pub port: Option<String>,
+    pub transport: TransportConfig,
+    /// Records all traffic to this file, see [`audiomixer_app2::protocol::Capture`].
+    pub capture: Option<PathBuf>,
+    /// Lets the settings page bind sliders to `cmd:` targets, which run
+    /// through the shell. Commands declared by the device are never run.
+    pub allow_commands: bool,
+    /// Picked by buttons bound to `profile:<n>`, counting from 1.
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            matchers: vec![DeviceMatcher {
+                vid: 0x303a,
+                pid: 0x8145,
+                product: None,
+                serial_number: None,
+            }],
+            port: None,
+            transport: TransportConfig::default(),
+            capture: None,
+            allow_commands: false,
+            profiles: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the config file, falling back to the defaults when it is
+    /// missing or invalid.
+    pub fn load() -> Self {
+        Self::load_from(config_path().as_deref(), |name| std::env::var_os(name))
+    }
+
+    /// [`Config::load`] reading `path` and the environment through `var`.
+    fn load_from(path: Option<&Path>, var: impl Fn(&str) -> Option<OsString>) -> Self {
+        let mut config = match path {
+            Some(path) if path.exists() => match std::fs::read(path)
+                .map_err(|e| e.to_string())
+                .and_then(|raw| serde_json::from_slice::<Config>(&raw).map_err(|e| e.to_string()))
+            {
+                Ok(config) => {
+                    println!("Loaded config from {}", path.display());
+                    config
+                }
+                Err(e) => {
+                    eprintln!("Ignoring invalid config {}: {}", path.display(), e);
+                    Config::default()
+                }
+            },
+            _ => Config::default(),
+        };
+
+        if let Some(port) = var(PORT_ENV).and_then(|port| port.into_string().ok()) {
+            config.port = Some(port);
+        }
+        if let Some(path) = var(CAPTURE_ENV) {
+            config.capture = Some(PathBuf::from(path));
+        }
+        if let Some(path) = var(REPLAY_ENV) {
+            config.transport = TransportConfig::Replay {
+                path: PathBuf::from(path),
+            };
+        }
+
+        config
+    }
+}
+
+/// `$AUDIOMIXER_CONFIG`, or `audiomixer/config.json` in the user config dir.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("audiomixer").join("config.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_env(_: &str) -> Option<OsString> {
+        None
+    }
+
+    /// Loads `json` as if it was the config file.
+    fn load(name: &str, json: &str, var: impl Fn(&str) -> Option<OsString>) -> Config {
+        let path =
+            std::env::temp_dir().join(format!("audiomixer-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, json).unwrap();
+        let config = Config::load_from(Some(&path), var);
+        std::fs::remove_file(&path).unwrap();
+        config
+    }
+
+    #[test]
+    fn missing_fields_keep_their_defaults() {
+        assert_eq!(load("empty", "{}", no_env), Config::default());
+        assert_eq!(
+            load("port", r#"{"port": "/dev/ttyUSB0"}"#, no_env),
+            Config {
+                port: Some("/dev/ttyUSB0".to_string()),
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn parses_every_field() {
+        let config = load(
+            "full",
+            r#"{
+                "matchers": [{"vid": 4660, "pid": 22136, "product": "Mixer", "serial_number": "A1"}],
+                "transport": {"type": "tcp", "address": "mixer.local:5000"},
+                "capture": "/tmp/traffic.jsonl",
+                "allow_commands": true,
+                "profiles": [{"name": "Call", "sliders": [{"volume": 30}, {"target": "app:zoom", "muted": false}]}]
+            }"#,
+            no_env,
+        );
+        assert_eq!(
+            config,
+            Config {
+                matchers: vec![DeviceMatcher {
+                    vid: 0x1234,
+                    pid: 0x5678,
+                    product: Some("Mixer".to_string()),
+                    serial_number: Some("A1".to_string()),
+                }],
+                port: None,
+                transport: TransportConfig::Tcp {
+                    address: "mixer.local:5000".to_string()
+                },
+                capture: Some(PathBuf::from("/tmp/traffic.jsonl")),
+                allow_commands: true,
+                profiles: vec![Profile {
+                    name: "Call".to_string(),
+                    sliders: vec![
+                        ProfileSlider {
+                            volume: Some(30),
+                            ..ProfileSlider::default()
+                        },
+                        ProfileSlider {
+                            volume: None,
+                            muted: Some(false),
+                            target: Some("app:zoom".to_string()),
+                        },
+                    ],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn unusable_files_fall_back_to_defaults() {
+        assert_eq!(load("invalid", "{\"port\": 5", no_env), Config::default());
+        assert_eq!(
+            load(
+                "unknown-transport",
+                r#"{"transport": {"type": "bluetooth"}}"#,
+                no_env
+            ),
+            Config::default()
+        );
+        let missing = std::env::temp_dir().join("audiomixer-missing-config.json");
+        assert_eq!(Config::load_from(Some(&missing), no_env), Config::default());
+        assert_eq!(Config::load_from(None, no_env), Config::default());
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let env = |name: &str| match name {
+            PORT_ENV => Some(OsString::from("/dev/pts/3")),
+            CAPTURE_ENV => Some(OsString::from("/tmp/env.jsonl")),
+            REPLAY_ENV => Some(OsString::from("/tmp/replay.jsonl")),
+            _ => None,
+        };
+        let config = load(
+            "env",
+            r#"{
+                "port": "/dev/ttyACM0",
+                "transport": {"type": "unix", "path": "/run/mixer.sock"},
+                "capture": "/tmp/file.jsonl"
+            }"#,
+            env,
+        );
+        assert_eq!(config.port.as_deref(), Some("/dev/pts/3"));
+        assert_eq!(config.capture, Some(PathBuf::from("/tmp/env.jsonl")));
+        assert_eq!(
+            config.transport,
+            TransportConfig::Replay {
+                path: PathBuf::from("/tmp/replay.jsonl")
+            }
+        );
+        // Without a file the overrides apply on top of the defaults
+        assert_eq!(
+            Config::load_from(None, env).port.as_deref(),
+            Some("/dev/pts/3")
+        );
+    }
+}
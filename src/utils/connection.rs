@@ -0,0 +1,192 @@
+use std::{path::PathBuf, time::Duration};
+
+use serialport::SerialPort;
+use smol::Timer;
+
+use crate::utils::{
+    Config, DeviceMatcher, PortCandidate, READ_TIMEOUT, Replay, Transport, TransportConfig,
+    connect_tcp, find_serial_ports,
+};
+
+/// Where the link to the device currently stands.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ConnectionState {
+    #[default]
+    Scanning,
+    PortFound(String),
+    Opening(String),
+    PermissionDenied(String),
+    Handshaking,
+    Connected,
+    Lost,
+    Error(String),
+}
+
+impl ConnectionState {
+    /// Whether this state should be remembered as the last error.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::PermissionDenied(_) | ConnectionState::Error(_)
+        )
+    }
+}
+
+impl std::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConnectionState::Scanning => write!(f, "Looking for a device..."),
+            ConnectionState::PortFound(port) => write!(f, "Found a device on {port}"),
+            ConnectionState::Opening(port) => write!(f, "Opening {port}..."),
+            ConnectionState::PermissionDenied(port) => write!(f, "Permission denied for {port}"),
+            ConnectionState::Handshaking => write!(f, "Talking to the device..."),
+            ConnectionState::Connected => write!(f, "Connected"),
+            ConnectionState::Lost => write!(f, "Device disconnected"),
+            ConnectionState::Error(reason) => write!(f, "Error: {reason}"),
+        }
+    }
+}
+
+/// How often opening a busy or not yet ready port is attempted.
+pub const OPEN_RETRIES: u32 = 3;
+pub const OPEN_RETRY_DELAY: Duration = Duration::from_millis(500);
+/// File name suggested for the generated udev rule.
+pub const UDEV_RULE_FILE: &str = "99-audiomixer.rules";
+
+/// Why a serial port could not be opened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OpenError {
+    /// EACCES, the user is not allowed to access the tty.
+    PermissionDenied,
+    /// EBUSY, another program holds the port.
+    Busy,
+    /// ENOENT, the device node vanished or is not created yet.
+    NotFound,
+    Other(String),
+}
+
+impl OpenError {
+    pub fn classify(error: &serialport::Error) -> Self {
+        match error.kind() {
+            serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied) => {
+                OpenError::PermissionDenied
+            }
+            // serialport reports EBUSY as NoDevice
+            serialport::ErrorKind::NoDevice => OpenError::Busy,
+            serialport::ErrorKind::Io(std::io::ErrorKind::NotFound) => OpenError::NotFound,
+            _ => OpenError::Other(error.to_string()),
+        }
+    }
+
+    /// Busy and missing ports usually sort themselves out within a moment,
+    /// e.g. while udev is still setting up the node or ModemManager probes it.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, OpenError::Busy | OpenError::NotFound)
+    }
+
+    pub fn into_state(self, port: String) -> ConnectionState {
+        match self {
+            OpenError::PermissionDenied => ConnectionState::PermissionDenied(port),
+            OpenError::Busy => {
+                ConnectionState::Error(format!("{port} is busy, is another program using it?"))
+            }
+            OpenError::NotFound => ConnectionState::Error(format!("{port} disappeared")),
+            OpenError::Other(reason) => ConnectionState::Error(reason),
+        }
+    }
+}
+
+/// Opens the serial port at `path`, retrying errors that tend to be
+/// temporary.
+pub async fn open_port(path: &str) -> Result<Box<dyn SerialPort>, OpenError> {
+    let mut attempt = 1;
+    loop {
+        let error = match serialport::new(path, 115_200)
+            .timeout(READ_TIMEOUT)
+            .dtr_on_open(true)
+            .open()
+        {
+            Ok(port) => return Ok(port),
+            Err(e) => {
+                eprintln!(
+                    "Failed to open {} (attempt {}/{}): {}",
+                    path, attempt, OPEN_RETRIES, e
+                );
+                OpenError::classify(&e)
+            }
+        };
+
+        if !error.is_retryable() || attempt >= OPEN_RETRIES {
+            return Err(error);
+        }
+        attempt += 1;
+        Timer::after(OPEN_RETRY_DELAY).await;
+    }
+}
+
+/// Opens the stream to the mixer found at `port_name` over `transport`.
+pub async fn open_transport(
+    transport: &TransportConfig,
+    port_name: &str,
+) -> Result<Box<dyn Transport>, OpenError> {
+    let stream: std::io::Result<Box<dyn Transport>> = match transport {
+        TransportConfig::Serial => return Ok(Box::new(open_port(port_name).await?)),
+        TransportConfig::Tcp { address } => {
+            connect_tcp(address).map(|stream| Box::new(stream) as Box<dyn Transport>)
+        }
+        #[cfg(unix)]
+        TransportConfig::Unix { path } => {
+            crate::utils::connect_unix(path).map(|stream| Box::new(stream) as Box<dyn Transport>)
+        }
+        #[cfg(not(unix))]
+        TransportConfig::Unix { .. } => {
+            return Err(OpenError::Other(
+                "Unix sockets are not supported on this platform".to_string(),
+            ));
+        }
+        TransportConfig::Replay { path } => {
+            Replay::open(path).map(|replay| Box::new(replay) as Box<dyn Transport>)
+        }
+    };
+
+    // Network peers come and go, the scanner simply tries again
+    stream.map_err(|e| {
+        eprintln!("Failed to connect to {}: {}", port_name, e);
+        OpenError::Other(format!("Failed to connect to {port_name}: {e}"))
+    })
+}
+
+/// Every mixer the configured transport can reach. Sockets are always
+/// reported, whether anything listens there is up to the session to find out.
+pub fn find_endpoints(config: &Config) -> Result<Vec<PortCandidate>, serialport::Error> {
+    match &config.transport {
+        TransportConfig::Serial => find_serial_ports(config),
+        transport => Ok(vec![PortCandidate {
+            port_name: transport.to_string(),
+            usb_info: None,
+            selected: true,
+            reason: "configured endpoint".to_string(),
+        }]),
+    }
+}
+
+/// udev rules granting the logged in user access to every configured device.
+pub fn udev_rule(matchers: &[DeviceMatcher]) -> String {
+    matchers
+        .iter()
+        .map(|matcher| {
+            format!(
+                "SUBSYSTEM==\"tty\", ATTRS{{idVendor}}==\"{:04x}\", ATTRS{{idProduct}}==\"{:04x}\", MODE=\"0660\", GROUP=\"dialout\", TAG+=\"uaccess\"\n",
+                matcher.vid, matcher.pid
+            )
+        })
+        .collect()
+}
+
+/// Writes [`udev_rule`] to a temporary file the user can copy into
+/// `/etc/udev/rules.d`.
+pub fn write_udev_rule(matchers: &[DeviceMatcher]) -> std::io::Result<PathBuf> {
+    let path = std::env::temp_dir().join(UDEV_RULE_FILE);
+    std::fs::write(&path, udev_rule(matchers))?;
+    Ok(path)
+}
@@ -0,0 +1,578 @@
+use std::{process::Command, thread};
+
+use crate::{
+    Device,
+    utils::{
+        ButtonEvent, ButtonEventKind, Config, EncoderEvent, MAX_POSITION, Profile, VolumeAction,
+        percent_position,
+    },
+};
+
+/// Volume change per encoder detent, in percent.
+pub const ENCODER_STEP: u8 = 2;
+
+/// What a device button is bound to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum ButtonAction {
+    #[default]
+    None,
+    /// Mutes or unmutes the slider at this index.
+    ToggleMute(usize),
+    PlayPause,
+    NextTrack,
+    PreviousTrack,
+    /// Applies the profile at this index of [`Config::profiles`].
+    SwitchProfile(usize),
+}
+
+impl ButtonAction {
+    /// Parses a binding declared by the device: `mute:<channel>`,
+    /// `play-pause`, `next`, `previous` or `profile:<number>`.
+    pub fn parse(action: &str) -> Self {
+        match action.split_once(':') {
+            Some(("mute", channel)) => match channel.parse::<usize>() {
+                Ok(channel) if channel > 0 => ButtonAction::ToggleMute(channel - 1),
+                _ => unknown_action(action),
+            },
+            Some(("profile", profile)) => match profile.parse::<usize>() {
+                Ok(profile) if profile > 0 => ButtonAction::SwitchProfile(profile - 1),
+                _ => unknown_action(action),
+            },
+            _ => match action {
+                "" => ButtonAction::None,
+                "play-pause" => ButtonAction::PlayPause,
+                "next" => ButtonAction::NextTrack,
+                "previous" => ButtonAction::PreviousTrack,
+                _ => unknown_action(action),
+            },
+        }
+    }
+
+    /// The binding as the device declares it, what [`ButtonAction::parse`]
+    /// reads back.
+    pub fn binding(&self) -> String {
+        match self {
+            ButtonAction::None => String::new(),
+            ButtonAction::ToggleMute(index) => format!("mute:{}", index + 1),
+            ButtonAction::PlayPause => "play-pause".to_string(),
+            ButtonAction::NextTrack => "next".to_string(),
+            ButtonAction::PreviousTrack => "previous".to_string(),
+            ButtonAction::SwitchProfile(index) => format!("profile:{}", index + 1),
+        }
+    }
+
+    /// Every binding that makes sense for a device with `sliders` sliders,
+    /// given `profiles` configured profiles.
+    pub fn options(sliders: usize, profiles: usize) -> Vec<Self> {
+        let mut options = vec![ButtonAction::None];
+        options.extend((0..sliders).map(ButtonAction::ToggleMute));
+        options.extend([
+            ButtonAction::PlayPause,
+            ButtonAction::NextTrack,
+            ButtonAction::PreviousTrack,
+        ]);
+        options.extend((0..profiles).map(ButtonAction::SwitchProfile));
+        options
+    }
+}
+
+impl std::fmt::Display for ButtonAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ButtonAction::None => write!(f, "Nothing"),
+            ButtonAction::ToggleMute(index) => write!(f, "Mute slider {}", index + 1),
+            ButtonAction::PlayPause => write!(f, "Play/pause"),
+            ButtonAction::NextTrack => write!(f, "Next track"),
+            ButtonAction::PreviousTrack => write!(f, "Previous track"),
+            ButtonAction::SwitchProfile(index) => write!(f, "Profile {}", index + 1),
+        }
+    }
+}
+
+/// What a rotary encoder is bound to.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum EncoderAction {
+    #[default]
+    None,
+    /// Turns the volume of the slider at this index up and down.
+    Volume(usize),
+    /// Skips a track forward or back per event, depending on the direction.
+    Tracks,
+}
+
+impl EncoderAction {
+    /// Parses a binding declared by the device: `volume:<channel>` or
+    /// `tracks`.
+    pub fn parse(action: &str) -> Self {
+        match action.split_once(':') {
+            Some(("volume", channel)) => match channel.parse::<usize>() {
+                Ok(channel) if channel > 0 => EncoderAction::Volume(channel - 1),
+                _ => unknown_action(action),
+            },
+            _ => match action {
+                "" => EncoderAction::None,
+                "tracks" => EncoderAction::Tracks,
+                _ => unknown_action(action),
+            },
+        }
+    }
+
+    /// Every binding that makes sense for a device with `sliders` sliders.
+    pub fn options(sliders: usize) -> Vec<Self> {
+        let mut options = vec![EncoderAction::None];
+        options.extend((0..sliders).map(EncoderAction::Volume));
+        options.push(EncoderAction::Tracks);
+        options
+    }
+}
+
+impl std::fmt::Display for EncoderAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncoderAction::None => write!(f, "Nothing"),
+            EncoderAction::Volume(index) => write!(f, "Volume of slider {}", index + 1),
+            EncoderAction::Tracks => write!(f, "Skip tracks"),
+        }
+    }
+}
+
+fn unknown_action<T: Default>(action: &str) -> T {
+    eprintln!("Unknown control action \"{}\", leaving it unbound", action);
+    T::default()
+}
+
+/// The option after `current`, wrapping around, so a single button can cycle
+/// through every binding.
+pub fn next_option<T: Clone + PartialEq>(options: &[T], current: &T) -> T {
+    let index = options
+        .iter()
+        .position(|option| option == current)
+        .map_or(0, |index| (index + 1) % options.len());
+    options[index].clone()
+}
+
+/// Runs whatever the user bound to a button, profiles come from `config`.
+///
+/// Buttons with a long press binding act on release, so holding them doesn't
+/// trigger the short press as well. The others act as soon as they go down.
+pub fn handle_button(device: &mut Device, event: &ButtonEvent, config: &Config) {
+    let Some(button) = device.buttons.get_mut(event.button as usize - 1) else {
+        eprintln!("Event for unknown button {}", event.button);
+        return;
+    };
+
+    let action = match event.kind {
+        ButtonEventKind::Press if button.long_press == ButtonAction::None => button.press.clone(),
+        ButtonEventKind::Press => {
+            button.held_long = false;
+            return;
+        }
+        ButtonEventKind::LongPress => {
+            button.held_long = true;
+            button.long_press.clone()
+        }
+        ButtonEventKind::Release
+            if button.long_press != ButtonAction::None && !button.held_long =>
+        {
+            button.press.clone()
+        }
+        ButtonEventKind::Release => return,
+    };
+
+    println!("Button {} runs \"{}\"", event.button, action.binding());
+    match action {
+        ButtonAction::None => {}
+        ButtonAction::ToggleMute(index) => device.toggle_mute(index),
+        ButtonAction::PlayPause => media_command("play-pause"),
+        ButtonAction::NextTrack => media_command("next"),
+        ButtonAction::PreviousTrack => media_command("previous"),
+        ButtonAction::SwitchProfile(index) => match config.profiles.get(index) {
+            Some(profile) => apply_profile(device, profile, config.allow_commands),
+            None => eprintln!("There is no profile {}", index + 1),
+        },
+    }
+}
+
+/// Rebinds, mutes and moves the sliders the profile mentions. Targets are
+/// checked like the ones entered on the settings page, the moves go out
+/// together, see [`Device::set_positions`].
+pub fn apply_profile(device: &mut Device, profile: &Profile, allow_commands: bool) {
+    println!("Switching to profile \"{}\"", profile.name);
+    let mut positions = device
+        .sliders
+        .iter()
+        .map(|slider| slider.position)
+        .collect::<Vec<_>>();
+    for (index, settings) in profile.sliders.iter().enumerate() {
+        let Some(slider) = device.sliders.get_mut(index) else {
+            eprintln!(
+                "Profile \"{}\" has more sliders than the device",
+                profile.name
+            );
+            break;
+        };
+        if let Some(target) = &settings.target {
+            match VolumeAction::from_user(target, allow_commands) {
+                Ok(action) => slider.set_volume_action = action,
+                Err(e) => eprintln!("Profile \"{}\", slider {}: {}", profile.name, index + 1, e),
+            }
+        }
+        if let Some(muted) = settings.muted {
+            device.set_muted(index, muted);
+        }
+        if let Some(volume) = settings.volume {
+            positions[index] = percent_position(volume.min(100));
+        }
+    }
+    device.set_positions(&positions);
+}
+
+/// Runs whatever the user bound to an encoder.
+pub fn handle_encoder(device: &mut Device, event: &EncoderEvent) {
+    let Some(encoder) = device.encoders.get(event.encoder as usize - 1) else {
+        eprintln!("Event for unknown encoder {}", event.encoder);
+        return;
+    };
+
+    match encoder.action {
+        EncoderAction::None => {}
+        EncoderAction::Volume(index) => {
+            let Some(slider) = device.sliders.get(index) else {
+                return;
+            };
+            let step = percent_position(ENCODER_STEP) as i32;
+            let position =
+                (slider.position as i32 + event.steps as i32 * step).clamp(0, MAX_POSITION as i32);
+            device.set_position(index, position as u16);
+        }
+        EncoderAction::Tracks if event.steps > 0 => media_command("next"),
+        EncoderAction::Tracks if event.steps < 0 => media_command("previous"),
+        EncoderAction::Tracks => {}
+    }
+}
+
+/// Controls the active media player through MPRIS.
+fn media_command(command: &'static str) {
+    thread::spawn(move || {
+        if let Err(e) = Command::new("playerctl").arg(command).status() {
+            eprintln!("Failed to run playerctl {}: {}", command, e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ButtonData, DeviceInfo, EncoderData, SliderData,
+        utils::{
+            Capabilities, CommandsOut, FirmwareVersion, HelloInfo, PROTOCOL_VERSION, ProfileSlider,
+        },
+    };
+
+    fn device(buttons: Vec<(ButtonAction, ButtonAction)>, encoders: Vec<EncoderAction>) -> Device {
+        let slider = |name: &str| SliderData {
+            name: name.to_string(),
+            position: percent_position(50),
+            muted: false,
+            set_volume_action: VolumeAction::Print,
+            device_action: String::new(),
+        };
+        Device {
+            sliders: vec![slider("One"), slider("Two")],
+            buttons: buttons
+                .into_iter()
+                .map(|(press, long_press)| ButtonData {
+                    name: "Button".to_string(),
+                    press,
+                    long_press,
+                    held_long: false,
+                })
+                .collect(),
+            encoders: encoders
+                .into_iter()
+                .map(|action| EncoderData {
+                    name: "Encoder".to_string(),
+                    action,
+                })
+                .collect(),
+            ..Device::default()
+        }
+    }
+
+    fn press(device: &mut Device, config: &Config, kinds: &[ButtonEventKind]) {
+        for &kind in kinds {
+            handle_button(device, &ButtonEvent { button: 1, kind }, config);
+        }
+    }
+
+    fn muted(device: &Device) -> Vec<bool> {
+        device.sliders.iter().map(|slider| slider.muted).collect()
+    }
+
+    #[test]
+    fn parses_button_bindings() {
+        let cases = [
+            ("", ButtonAction::None),
+            ("mute:1", ButtonAction::ToggleMute(0)),
+            ("mute:12", ButtonAction::ToggleMute(11)),
+            ("play-pause", ButtonAction::PlayPause),
+            ("next", ButtonAction::NextTrack),
+            ("previous", ButtonAction::PreviousTrack),
+            ("profile:2", ButtonAction::SwitchProfile(1)),
+            ("mute:0", ButtonAction::None),
+            ("mute:x", ButtonAction::None),
+            ("profile:", ButtonAction::None),
+            ("shuffle", ButtonAction::None),
+        ];
+        for (binding, expected) in cases {
+            assert_eq!(ButtonAction::parse(binding), expected, "{binding:?}");
+        }
+    }
+
+    #[test]
+    fn button_bindings_round_trip() {
+        let options = ButtonAction::options(3, 2);
+        assert_eq!(options.len(), 1 + 3 + 3 + 2);
+        for action in options {
+            assert_eq!(ButtonAction::parse(&action.binding()), action);
+        }
+        let labels = [
+            ButtonAction::None,
+            ButtonAction::ToggleMute(0),
+            ButtonAction::PlayPause,
+            ButtonAction::NextTrack,
+            ButtonAction::PreviousTrack,
+            ButtonAction::SwitchProfile(1),
+        ]
+        .map(|action| action.to_string());
+        assert_eq!(
+            labels,
+            [
+                "Nothing",
+                "Mute slider 1",
+                "Play/pause",
+                "Next track",
+                "Previous track",
+                "Profile 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_encoder_bindings() {
+        assert_eq!(EncoderAction::parse(""), EncoderAction::None);
+        assert_eq!(EncoderAction::parse("volume:2"), EncoderAction::Volume(1));
+        assert_eq!(EncoderAction::parse("tracks"), EncoderAction::Tracks);
+        assert_eq!(EncoderAction::parse("volume:0"), EncoderAction::None);
+        assert_eq!(EncoderAction::parse("scroll"), EncoderAction::None);
+        assert_eq!(EncoderAction::Volume(1).to_string(), "Volume of slider 2");
+    }
+
+    #[test]
+    fn options_cycle_around() {
+        let options = EncoderAction::options(1);
+        assert_eq!(
+            next_option(&options, &EncoderAction::None),
+            EncoderAction::Volume(0)
+        );
+        assert_eq!(
+            next_option(&options, &EncoderAction::Tracks),
+            EncoderAction::None
+        );
+        // A binding that is no longer offered starts over
+        assert_eq!(
+            next_option(&options, &EncoderAction::Volume(5)),
+            EncoderAction::None
+        );
+    }
+
+    #[test]
+    fn buttons_without_long_press_act_when_pressed() {
+        let config = Config::default();
+        let mut device = device(
+            vec![(ButtonAction::ToggleMute(0), ButtonAction::None)],
+            vec![],
+        );
+
+        press(&mut device, &config, &[ButtonEventKind::Press]);
+        assert_eq!(muted(&device), [true, false]);
+        press(&mut device, &config, &[ButtonEventKind::Release]);
+        assert_eq!(muted(&device), [true, false]);
+        // Holding changes nothing either
+        press(&mut device, &config, &[ButtonEventKind::LongPress]);
+        assert_eq!(muted(&device), [true, false]);
+    }
+
+    #[test]
+    fn long_press_replaces_the_short_press() {
+        let config = Config::default();
+        let mut device = device(
+            vec![(ButtonAction::ToggleMute(0), ButtonAction::ToggleMute(1))],
+            vec![],
+        );
+
+        press(&mut device, &config, &[ButtonEventKind::Press]);
+        assert_eq!(muted(&device), [false, false]);
+        press(&mut device, &config, &[ButtonEventKind::Release]);
+        assert_eq!(muted(&device), [true, false]);
+
+        press(
+            &mut device,
+            &config,
+            &[ButtonEventKind::Press, ButtonEventKind::LongPress],
+        );
+        assert_eq!(muted(&device), [true, true]);
+        press(&mut device, &config, &[ButtonEventKind::Release]);
+        assert_eq!(muted(&device), [true, true]);
+
+        // The next short press is not mistaken for a long one
+        press(
+            &mut device,
+            &config,
+            &[ButtonEventKind::Press, ButtonEventKind::Release],
+        );
+        assert_eq!(muted(&device), [false, true]);
+    }
+
+    #[test]
+    fn profile_button_applies_the_profile() {
+        let config = Config {
+            profiles: vec![Profile {
+                name: "Call".to_string(),
+                sliders: vec![
+                    ProfileSlider {
+                        volume: Some(30),
+                        ..ProfileSlider::default()
+                    },
+                    ProfileSlider {
+                        volume: None,
+                        muted: Some(true),
+                        target: Some("app:audiomixer-test-player".to_string()),
+                    },
+                    // Past the last slider
+                    ProfileSlider {
+                        volume: Some(10),
+                        ..ProfileSlider::default()
+                    },
+                ],
+            }],
+            ..Config::default()
+        };
+        let mut device = device(
+            vec![
+                (ButtonAction::SwitchProfile(0), ButtonAction::None),
+                (ButtonAction::SwitchProfile(1), ButtonAction::None),
+            ],
+            vec![],
+        );
+
+        press(&mut device, &config, &[ButtonEventKind::Press]);
+        assert_eq!(device.sliders[0].position, percent_position(30));
+        assert_eq!(device.sliders[0].set_volume_action, VolumeAction::Print);
+        assert_eq!(device.sliders[1].position, percent_position(50));
+        assert_eq!(
+            device.sliders[1].set_volume_action,
+            VolumeAction::App("audiomixer-test-player".to_string())
+        );
+        assert_eq!(muted(&device), [false, true]);
+
+        // A profile that is not configured changes nothing
+        device.sliders[0].position = 0;
+        handle_button(
+            &mut device,
+            &ButtonEvent {
+                button: 2,
+                kind: ButtonEventKind::Press,
+            },
+            &config,
+        );
+        assert_eq!(device.sliders[0].position, 0);
+    }
+
+    #[test]
+    fn profiles_move_sliders_in_one_frame() {
+        let (serial_out_tx, mut serial_out_rx) = futures_channel::mpsc::unbounded();
+        let mut device = device(vec![], vec![]);
+        device.info = Some(DeviceInfo {
+            port_name: "/dev/ttyACM0".to_string(),
+            usb_info: None,
+            hello: Some(HelloInfo {
+                protocol_version: PROTOCOL_VERSION,
+                firmware_version: FirmwareVersion {
+                    major: 1,
+                    minor: 0,
+                    patch: 0,
+                },
+                capabilities: Capabilities::BULK,
+            }),
+            serial_out_tx,
+        });
+        let profile = Profile {
+            name: "Quiet".to_string(),
+            sliders: vec![
+                ProfileSlider {
+                    volume: Some(10),
+                    ..ProfileSlider::default()
+                },
+                ProfileSlider {
+                    volume: Some(20),
+                    ..ProfileSlider::default()
+                },
+            ],
+        };
+
+        apply_profile(&mut device, &profile, false);
+        assert_eq!(
+            serial_out_rx.try_recv().unwrap(),
+            CommandsOut::SetVolumes(vec![10, 20])
+        );
+        assert!(serial_out_rx.try_recv().is_err());
+
+        // Nothing moved, nothing to send
+        apply_profile(&mut device, &profile, false);
+        assert!(serial_out_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn profiles_only_run_commands_with_the_opt_in() {
+        let profile = Profile {
+            name: "Script".to_string(),
+            sliders: vec![ProfileSlider {
+                target: Some("cmd:true".to_string()),
+                ..ProfileSlider::default()
+            }],
+        };
+        let mut device = device(vec![], vec![]);
+        apply_profile(&mut device, &profile, false);
+        assert_eq!(device.sliders[0].set_volume_action, VolumeAction::Print);
+    }
+
+    #[test]
+    fn encoders_step_the_volume() {
+        let mut device = device(vec![], vec![EncoderAction::Volume(1), EncoderAction::None]);
+        let turn = |device: &mut Device, encoder: u8, steps: i8| {
+            handle_encoder(device, &EncoderEvent { encoder, steps });
+        };
+
+        turn(&mut device, 1, 3);
+        assert_eq!(
+            device.sliders[1].position,
+            percent_position(50) + 3 * percent_position(ENCODER_STEP)
+        );
+        turn(&mut device, 1, -2);
+        assert_eq!(
+            device.sliders[1].position,
+            percent_position(50) + percent_position(ENCODER_STEP)
+        );
+        turn(&mut device, 1, 127);
+        assert_eq!(device.sliders[1].position, MAX_POSITION);
+        turn(&mut device, 1, -128);
+        assert_eq!(device.sliders[1].position, 0);
+
+        // Unbound and unknown encoders are ignored
+        turn(&mut device, 2, 5);
+        turn(&mut device, 3, 5);
+        assert_eq!(device.sliders[0].position, percent_position(50));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    Device, SliderData,
+    utils::{Capabilities, CommandsOut, Icon, SetDisplayProps, target_label},
+};
+
+const SPEAKER: Icon = Icon([
+    0b0000000000000000,
+    0b0000000100000000,
+    0b0000001100000000,
+    0b0000011100010000,
+    0b0000111100001000,
+    0b0111111100100100,
+    0b0111111100010100,
+    0b0111111100010100,
+    0b0111111100010100,
+    0b0111111100100100,
+    0b0000111100001000,
+    0b0000011100010000,
+    0b0000001100000000,
+    0b0000000100000000,
+    0b0000000000000000,
+    0b0000000000000000,
+]);
+
+const SPEAKER_MUTED: Icon = Icon([
+    0b0000000000000000,
+    0b0000000100000000,
+    0b0000001100000000,
+    0b0000011100000000,
+    0b0000111100000000,
+    0b0111111101000100,
+    0b0111111100101000,
+    0b0111111100010000,
+    0b0111111100101000,
+    0b0111111101000100,
+    0b0000111100000000,
+    0b0000011100000000,
+    0b0000001100000000,
+    0b0000000100000000,
+    0b0000000000000000,
+    0b0000000000000000,
+]);
+
+/// What the display of a 1-based channel shows for `slider`.
+pub fn display_state(channel: u8, slider: &SliderData) -> SetDisplayProps {
+    SetDisplayProps {
+        channel,
+        label: target_label(slider),
+        percentage: slider.volume(),
+        icon: Some(if slider.muted { SPEAKER_MUTED } else { SPEAKER }),
+    }
+}
+
+/// Sends the displays whose content changed since the last refresh, so a
+/// new binding or volume shows up without redrawing every channel.
+pub fn refresh_displays(device: &mut Device) {
+    if device.info.is_none() || !device.capabilities().contains(Capabilities::DISPLAY) {
+        return;
+    }
+
+    let displays = device
+        .sliders
+        .iter()
+        .enumerate()
+        .map(|(index, slider)| display_state(index as u8 + 1, slider))
+        .collect::<Vec<_>>();
+    for (index, display) in displays.iter().enumerate() {
+        if device.displays.get(index) != Some(display) {
+            device.send_command(CommandsOut::SetDisplay(display.clone()));
+        }
+    }
+    device.displays = displays;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        DeviceInfo,
+        utils::{FirmwareVersion, HelloInfo, MAX_POSITION, PROTOCOL_VERSION, VolumeAction},
+    };
+    use futures_channel::mpsc::UnboundedReceiver;
+
+    fn slider(name: &str, action: VolumeAction) -> SliderData {
+        SliderData {
+            name: name.to_string(),
+            position: MAX_POSITION / 2,
+            muted: false,
+            set_volume_action: action,
+            device_action: String::new(),
+        }
+    }
+
+    fn device(capabilities: Capabilities) -> (Device, UnboundedReceiver<CommandsOut>) {
+        let (serial_out_tx, serial_out_rx) = futures_channel::mpsc::unbounded();
+        let device = Device {
+            info: Some(DeviceInfo {
+                port_name: "/dev/ttyACM0".to_string(),
+                usb_info: None,
+                hello: Some(HelloInfo {
+                    protocol_version: PROTOCOL_VERSION,
+                    firmware_version: FirmwareVersion {
+                        major: 1,
+                        minor: 0,
+                        patch: 0,
+                    },
+                    capabilities,
+                }),
+                serial_out_tx,
+            }),
+            sliders: vec![
+                slider("Music", VolumeAction::App("spotify".to_string())),
+                slider("Mic", VolumeAction::Source("default".to_string())),
+            ],
+            ..Device::default()
+        };
+        (device, serial_out_rx)
+    }
+
+    fn sent(rx: &mut UnboundedReceiver<CommandsOut>) -> Vec<CommandsOut> {
+        std::iter::from_fn(|| rx.try_recv().ok()).collect()
+    }
+
+    #[test]
+    fn displays_show_the_target_and_volume() {
+        let app = slider("Music", VolumeAction::App("spotify".to_string()));
+        assert_eq!(
+            display_state(1, &app),
+            SetDisplayProps {
+                channel: 1,
+                label: "spotify".to_string(),
+                percentage: 50,
+                icon: Some(SPEAKER),
+            }
+        );
+
+        let mut mic = slider("Mic", VolumeAction::Source("default".to_string()));
+        mic.position = MAX_POSITION;
+        mic.muted = true;
+        assert_eq!(
+            display_state(2, &mic),
+            SetDisplayProps {
+                channel: 2,
+                label: "Mic".to_string(),
+                percentage: 100,
+                icon: Some(SPEAKER_MUTED),
+            }
+        );
+    }
+
+    #[test]
+    fn only_changed_displays_are_sent() {
+        let (mut device, mut serial_out_rx) = device(Capabilities::DISPLAY);
+
+        refresh_displays(&mut device);
+        assert_eq!(sent(&mut serial_out_rx).len(), 2);
+        refresh_displays(&mut device);
+        assert!(sent(&mut serial_out_rx).is_empty());
+
+        device.sliders[1].position = 0;
+        refresh_displays(&mut device);
+        assert_eq!(
+            sent(&mut serial_out_rx),
+            [CommandsOut::SetDisplay(display_state(
+                2,
+                &device.sliders[1]
+            ))]
+        );
+
+        device.sliders[0].set_volume_action = VolumeAction::App("firefox".to_string());
+        refresh_displays(&mut device);
+        let display = display_state(1, &device.sliders[0]);
+        assert_eq!(display.label, "firefox");
+        assert_eq!(sent(&mut serial_out_rx), [CommandsOut::SetDisplay(display)]);
+
+        device.sliders[0].muted = true;
+        refresh_displays(&mut device);
+        assert_eq!(
+            sent(&mut serial_out_rx),
+            [CommandsOut::SetDisplay(display_state(
+                1,
+                &device.sliders[0]
+            ))]
+        );
+    }
+
+    #[test]
+    fn devices_without_displays_get_nothing() {
+        let (mut device, mut serial_out_rx) = device(Capabilities::LEDS);
+        refresh_displays(&mut device);
+        assert!(sent(&mut serial_out_rx).is_empty());
+        assert!(device.displays.is_empty());
+    }
+}
@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use futures_channel::mpsc::UnboundedSender;
+use smol::Timer;
+
+use crate::{
+    ChannelSend, Device, SliderData,
+    utils::{Capabilities, CommandsOut, Rgb, SetLedProps, peak_level, target_running},
+};
+
+/// How often the LEDs and displays are brought in line with the app state.
+pub const FEEDBACK_INTERVAL: Duration = Duration::from_millis(50);
+
+const MUTED: Rgb = Rgb::new(255, 0, 0);
+const VU_LOW: Rgb = Rgb::new(0, 255, 0);
+const VU_HIGH: Rgb = Rgb::new(255, 160, 0);
+const VU_PEAK: Rgb = Rgb::new(255, 64, 0);
+const FULL: u8 = 255;
+/// Brightness of a channel whose target is not running.
+const DIM: u8 = 24;
+/// Peak levels from which the LED turns [`VU_HIGH`], about -6 dBFS, and
+/// [`VU_PEAK`], about -1 dBFS.
+const HIGH_LEVEL: f32 = 0.5;
+const PEAK_LEVEL: f32 = 0.89;
+
+/// What a channel's LED shows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedState {
+    pub color: Rgb,
+    pub brightness: u8,
+}
+
+/// Red when muted, dimmed when the target is not `running`, otherwise a VU
+/// colour for the `peak` level of the target's audio, see [`peak_level`].
+pub fn led_state(slider: &SliderData, running: bool, peak: f32) -> LedState {
+    if slider.muted {
+        return LedState {
+            color: MUTED,
+            brightness: FULL,
+        };
+    }
+
+    let color = if peak >= PEAK_LEVEL {
+        VU_PEAK
+    } else if peak >= HIGH_LEVEL {
+        VU_HIGH
+    } else {
+        VU_LOW
+    };
+    let brightness = if running { FULL } else { DIM };
+    LedState { color, brightness }
+}
+
+/// Sends the LEDs whose state changed since the last refresh.
+pub fn refresh_leds(device: &mut Device) {
+    if device.info.is_none() || !device.capabilities().contains(Capabilities::LEDS) {
+        return;
+    }
+
+    let leds = device
+        .sliders
+        .iter()
+        .map(|slider| {
+            let running = target_running(&slider.set_volume_action);
+            // Nothing to show for muted or missing targets, no need to record them
+            let peak = if running && !slider.muted {
+                peak_level(&slider.set_volume_action)
+            } else {
+                0.0
+            };
+            led_state(slider, running, peak)
+        })
+        .collect::<Vec<_>>();
+    for (index, led) in leds.iter().enumerate() {
+        if device.leds.get(index) != Some(led) {
+            device.send_command(CommandsOut::SetLed(SetLedProps {
+                channel: index as u8 + 1,
+                color: led.color,
+                brightness: led.brightness,
+            }));
+        }
+    }
+    device.leds = leds;
+}
+
+/// Asks the app to refresh the LEDs and displays every
+/// [`FEEDBACK_INTERVAL`]. Polling rather than reacting to changes, targets
+/// come and go on their own.
+pub async fn feedback_ticks(state_tx: UnboundedSender<ChannelSend>) {
+    while state_tx.unbounded_send(ChannelSend::FeedbackTick).is_ok() {
+        Timer::after(FEEDBACK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceInfo, utils::VolumeAction};
+
+    fn slider(muted: bool) -> SliderData {
+        SliderData {
+            name: "Slider".to_string(),
+            position: 0,
+            muted,
+            set_volume_action: VolumeAction::Print,
+            device_action: String::new(),
+        }
+    }
+
+    #[test]
+    fn muted_channels_are_red() {
+        let muted = LedState {
+            color: MUTED,
+            brightness: FULL,
+        };
+        assert_eq!(led_state(&slider(true), true, 1.0), muted);
+        assert_eq!(led_state(&slider(true), false, 0.0), muted);
+    }
+
+    #[test]
+    fn missing_targets_are_dim() {
+        assert_eq!(
+            led_state(&slider(false), false, 0.0),
+            LedState {
+                color: VU_LOW,
+                brightness: DIM,
+            }
+        );
+    }
+
+    #[test]
+    fn peaks_pick_the_colour() {
+        let cases = [
+            (0.0, VU_LOW),
+            (0.49, VU_LOW),
+            (HIGH_LEVEL, VU_HIGH),
+            (0.88, VU_HIGH),
+            (PEAK_LEVEL, VU_PEAK),
+            (1.0, VU_PEAK),
+        ];
+        for (peak, color) in cases {
+            assert_eq!(
+                led_state(&slider(false), true, peak),
+                LedState {
+                    color,
+                    brightness: FULL,
+                },
+                "{peak}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_changed_leds_are_sent() {
+        let (serial_out_tx, mut serial_out_rx) = futures_channel::mpsc::unbounded();
+        let mut device = Device {
+            info: Some(DeviceInfo {
+                port_name: "/dev/ttyACM0".to_string(),
+                usb_info: None,
+                hello: Some(crate::utils::HelloInfo {
+                    protocol_version: crate::utils::PROTOCOL_VERSION,
+                    firmware_version: crate::utils::FirmwareVersion {
+                        major: 1,
+                        minor: 0,
+                        patch: 0,
+                    },
+                    capabilities: Capabilities::LEDS,
+                }),
+                serial_out_tx,
+            }),
+            sliders: vec![slider(false), slider(false)],
+            ..Device::default()
+        };
+        let sent = |rx: &mut futures_channel::mpsc::UnboundedReceiver<CommandsOut>| {
+            std::iter::from_fn(|| rx.try_recv().ok()).collect::<Vec<_>>()
+        };
+
+        refresh_leds(&mut device);
+        assert_eq!(sent(&mut serial_out_rx).len(), 2);
+        refresh_leds(&mut device);
+        assert!(sent(&mut serial_out_rx).is_empty());
+
+        device.sliders[1].muted = true;
+        refresh_leds(&mut device);
+        assert_eq!(
+            sent(&mut serial_out_rx),
+            [CommandsOut::SetLed(SetLedProps {
+                channel: 2,
+                color: MUTED,
+                brightness: FULL,
+            })]
+        );
+    }
+}
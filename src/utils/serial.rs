@@ -1,147 +1,220 @@
-use serde::Deserialize;
 use serialport::UsbPortInfo;
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct SetVolumeProps {
-    pub channel: u8,
-    pub volume: u8,
-}
+use crate::utils::{Config, DeviceMatcher};
 
-#[derive(Clone, Debug, PartialEq)]
-pub enum CommandsOut {
-    RequestInfo,
-    SetVolume(SetVolumeProps),
+/// A port reported by the OS and whether it would be used for the mixer.
+#[derive(Clone, Debug)]
+pub struct PortCandidate {
+    pub port_name: String,
+    pub usb_info: Option<UsbPortInfo>,
+    pub selected: bool,
+    pub reason: String,
 }
 
-#[repr(u8)]
-pub enum CommandOut {
-    RequestInfo = 0x01,
-    SetVolume = 0x02,
+/// Lists every port `serialport` can see together with the reason it was or
+/// wasn't picked according to `config`.
+pub fn list_candidate_ports(config: &Config) -> Result<Vec<PortCandidate>, serialport::Error> {
+    let mut candidates = Vec::new();
+
+    for port in serialport::available_ports()? {
+        let usb_info = match port.port_type {
+            serialport::SerialPortType::UsbPort(info) => Some(info),
+            _ => None,
+        };
+
+        let (selected, reason) = match port_verdict(config, &port.port_name, usb_info.as_ref()) {
+            Ok(reason) => (true, reason),
+            Err(reason) => (false, reason),
+        };
+
+        candidates.push(PortCandidate {
+            port_name: port.port_name,
+            usb_info,
+            selected,
+            reason,
+        });
+    }
+
+    Ok(candidates)
 }
 
-#[repr(u8)]
-pub enum CommandIn {
-    SendInfo = 0x81,
-    SendVolume = 0x82,
+/// Why the port is picked, or why not. A configured port takes precedence over
+/// every matcher.
+fn port_verdict(
+    config: &Config,
+    port_name: &str,
+    usb_info: Option<&UsbPortInfo>,
+) -> Result<String, String> {
+    match (&config.port, usb_info) {
+        (Some(path), _) if path == port_name => Ok("configured port".to_string()),
+        (Some(path), _) => Err(format!("not the configured port {}", path)),
+        (None, Some(info)) => match_usb_port(&config.matchers, info),
+        (None, None) => Err("not a USB port".to_string()),
+    }
 }
 
-impl TryFrom<u8> for CommandIn {
-    type Error = ();
+fn match_usb_port(matchers: &[DeviceMatcher], info: &UsbPortInfo) -> Result<String, String> {
+    let mut reason = format!(
+        "{:04x}:{:04x} matches no configured device",
+        info.vid, info.pid
+    );
 
-    fn try_from(value: u8) -> Result<Self, Self::Error> {
-        match value {
-            0x81 => Ok(CommandIn::SendInfo),
-            0x82 => Ok(CommandIn::SendVolume),
-            _ => Err(()),
+    for matcher in matchers {
+        if matcher.vid != info.vid || matcher.pid != info.pid {
+            continue;
+        }
+        if let Some(product) = &matcher.product
+            && !info
+                .product
+                .as_deref()
+                .unwrap_or_default()
+                .contains(product.as_str())
+        {
+            reason = format!(
+                "product {:?} does not contain \"{}\"",
+                info.product, product
+            );
+            continue;
+        }
+        if let Some(serial_number) = &matcher.serial_number
+            && info.serial_number.as_ref() != Some(serial_number)
+        {
+            reason = format!(
+                "serial number {:?} is not {}",
+                info.serial_number, serial_number
+            );
+            continue;
         }
+        return Ok(format!("matches {}", matcher));
     }
-}
-
-#[derive(Clone, Debug, PartialEq, Deserialize)]
-pub struct DeviceSliderData {
-    pub name: String,
-    pub set_volume_action: String,
-}
 
-#[derive(Clone, Debug, PartialEq, Deserialize)]
-pub struct DeviceInfo {
-    pub sliders: Vec<DeviceSliderData>,
+    Err(reason)
 }
 
-#[derive(Clone, Debug, PartialEq)]
-#[allow(dead_code)]
-pub enum CommandsIn {
-    SendInfo(DeviceInfo),
-    SendVolume(VolumeInfo),
-}
+/// Picks every port that belongs to a mixer, see [`list_candidate_ports`].
+pub fn find_serial_ports(config: &Config) -> Result<Vec<PortCandidate>, serialport::Error> {
+    let mut ports = list_candidate_ports(config)?;
+    ports.retain(|candidate| candidate.selected);
+
+    // The configured port might not be enumerated, e.g. a pseudo terminal
+    if ports.is_empty()
+        && let Some(path) = &config.port
+    {
+        ports.push(PortCandidate {
+            port_name: path.clone(),
+            usb_info: None,
+            selected: true,
+            reason: "configured port".to_string(),
+        });
+    }
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct VolumeInfo {
-    pub channel: u8,
-    pub volume: u8,
+    Ok(ports)
 }
 
-pub fn find_serial_port(
-    vid: u16,
-    pid: u16,
-) -> Result<(String, UsbPortInfo), Box<dyn std::error::Error>> {
-    let ports = serialport::available_ports()?;
-
-    for port in ports {
-        if let serialport::SerialPortType::UsbPort(info) = &port.port_type {
-            if dbg!(info).vid == vid && info.pid == pid {
-                return Ok((port.port_name, info.clone()));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn port(product: Option<&str>, serial_number: Option<&str>) -> UsbPortInfo {
+        UsbPortInfo {
+            vid: 0x303a,
+            pid: 0x8145,
+            serial_number: serial_number.map(str::to_string),
+            manufacturer: None,
+            product: product.map(str::to_string),
         }
     }
 
-    Err("Device not found".into())
-}
-
-pub fn get_payload(command: CommandsOut) -> Vec<u8> {
-    let mut buffer = Vec::new();
-
-    match command {
-        CommandsOut::RequestInfo => {
-            buffer.push(CommandOut::RequestInfo as u8);
-            buffer.push(0x00); // channel (unused)
-        }
-        CommandsOut::SetVolume(props) => {
-            buffer.push(CommandOut::SetVolume as u8);
-            buffer.push(props.channel);
-            buffer.push(props.volume);
+    fn matcher(product: Option<&str>, serial_number: Option<&str>) -> DeviceMatcher {
+        DeviceMatcher {
+            vid: 0x303a,
+            pid: 0x8145,
+            product: product.map(str::to_string),
+            serial_number: serial_number.map(str::to_string),
         }
     }
 
-    buffer
-}
-
-pub fn get_received_payload(buffer: &[u8]) -> Result<CommandsIn, Box<dyn std::error::Error>> {
-    println!("Received buffer: {:#04X?}", buffer);
-    if buffer.len() < 2 {
-        return Err("Buffer too short".into());
-    }
-    let command = buffer[0];
-    let channel = buffer[1];
-
-    match CommandIn::try_from(command) {
-        Ok(CommandIn::SendInfo) => {
-            if buffer.len() < 3 {
-                return Err("Buffer too short for SendInfo".into());
-            }
-            let json_raw = &buffer[2..];
-            println!("{}", std::str::from_utf8(json_raw)?);
-            Ok(CommandsIn::SendInfo(serde_json::from_slice::<DeviceInfo>(
-                json_raw,
-            )?))
-        }
-        Ok(CommandIn::SendVolume) => {
-            let volume = buffer[2];
-            Ok(CommandsIn::SendVolume(VolumeInfo {
-                channel: channel,
-                volume,
-            }))
-        }
-        Err(_) => Err(format!("Unknown command received: {:#04X?}", command).into()),
+    #[test]
+    fn ids_have_to_match() {
+        let mut info = port(None, None);
+        assert_eq!(
+            match_usb_port(&[matcher(None, None)], &info),
+            Ok("matches 303a:8145".to_string())
+        );
+        info.pid = 0x1001;
+        assert_eq!(
+            match_usb_port(&[matcher(None, None)], &info),
+            Err("303a:1001 matches no configured device".to_string())
+        );
+        assert_eq!(
+            match_usb_port(&[], &port(None, None)),
+            Err("303a:8145 matches no configured device".to_string())
+        );
     }
-}
 
-pub fn send_command(port: &mut Box<dyn serialport::SerialPort>, command: CommandsOut) {
-    let mut buffer = Vec::new();
+    #[test]
+    fn product_and_serial_number_narrow_the_match() {
+        let matchers = [matcher(Some("Mixer"), Some("A1"))];
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("Audio Mixer v2"), Some("A1"))),
+            Ok("matches 303a:8145 product \"Mixer\" serial A1".to_string())
+        );
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("JTAG"), Some("A1"))),
+            Err("product Some(\"JTAG\") does not contain \"Mixer\"".to_string())
+        );
+        assert_eq!(
+            match_usb_port(&matchers, &port(None, Some("A1"))),
+            Err("product None does not contain \"Mixer\"".to_string())
+        );
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("Mixer"), Some("B2"))),
+            Err("serial number Some(\"B2\") is not A1".to_string())
+        );
+    }
 
-    match command {
-        CommandsOut::RequestInfo => {
-            buffer.push(CommandOut::RequestInfo as u8);
-            buffer.push(0x00); // channel (unused)
-        }
-        CommandsOut::SetVolume(props) => {
-            buffer.push(CommandOut::SetVolume as u8);
-            buffer.push(props.channel + 1);
-            buffer.push(props.volume);
-        }
+    #[test]
+    fn first_matching_matcher_wins() {
+        let matchers = [matcher(None, Some("A1")), matcher(Some("Mixer"), None)];
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("Mixer"), Some("B2"))),
+            Ok("matches 303a:8145 product \"Mixer\"".to_string())
+        );
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("Mixer"), Some("A1"))),
+            Ok("matches 303a:8145 serial A1".to_string())
+        );
+        // The reason names the last matcher that came close
+        assert_eq!(
+            match_usb_port(&matchers, &port(Some("JTAG"), Some("B2"))),
+            Err("product Some(\"JTAG\") does not contain \"Mixer\"".to_string())
+        );
     }
 
-    if let Err(e) = port.write_all(&buffer) {
-        eprintln!("Failed to send command: {}", e);
+    #[test]
+    fn configured_port_overrides_matchers() {
+        let config = Config {
+            port: Some("/dev/ttyACM1".to_string()),
+            ..Config::default()
+        };
+        let info = port(None, None);
+        assert_eq!(
+            port_verdict(&config, "/dev/ttyACM1", None),
+            Ok("configured port".to_string())
+        );
+        assert_eq!(
+            port_verdict(&config, "/dev/ttyACM0", Some(&info)),
+            Err("not the configured port /dev/ttyACM1".to_string())
+        );
+
+        let config = Config::default();
+        assert_eq!(
+            port_verdict(&config, "/dev/ttyACM0", Some(&info)),
+            Ok("matches 303a:8145".to_string())
+        );
+        assert_eq!(
+            port_verdict(&config, "/dev/ttyS0", None),
+            Err("not a USB port".to_string())
+        );
     }
 }
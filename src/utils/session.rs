@@ -0,0 +1,382 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures_lite::StreamExt;
+use serialport::UsbPortInfo;
+use smol::Timer;
+
+use crate::{
+    ChannelSend, DeviceInfo,
+    utils::{
+        Capabilities, Capture, CommandsIn, CommandsOut, Config, ConnectionState,
+        HEARTBEAT_INTERVAL, Heartbeat, IncompatibleProtocol, Link, LinkStats, OpenError,
+        PortCandidate, ProtocolError, Recording, Transport, TransportConfig, find_endpoints,
+        open_transport, percent_position, read_loop, sync_device, write_loop,
+    },
+};
+
+/// Identifies a mixer across reconnects: its USB serial number, or the port
+/// name when there is none.
+pub type DeviceId = String;
+
+pub fn device_id(port_name: &str, usb_info: Option<&UsbPortInfo>) -> DeviceId {
+    usb_info
+        .and_then(|info| info.serial_number.clone())
+        .unwrap_or_else(|| port_name.to_string())
+}
+
+/// Looks for mixers forever and starts a session for every one that is not
+/// connected yet.
+pub async fn scan_devices(config: Config, state_tx: UnboundedSender<ChannelSend>) {
+    let active: Arc<Mutex<HashSet<DeviceId>>> = Arc::default();
+    let capture = config
+        .capture
+        .as_ref()
+        .and_then(|path| match Capture::create(path) {
+            Ok(capture) => {
+                println!("Capturing traffic to {}", path.display());
+                Some(Arc::new(capture))
+            }
+            Err(e) => {
+                eprintln!("Failed to create capture {}: {}", path.display(), e);
+                None
+            }
+        });
+
+    println!("Starting device scan...");
+    loop {
+        match find_endpoints(&config) {
+            Ok(ports) => {
+                for PortCandidate {
+                    port_name,
+                    usb_info,
+                    ..
+                } in ports
+                {
+                    let id = device_id(&port_name, usb_info.as_ref());
+                    if !active.lock().unwrap().insert(id.clone()) {
+                        continue;
+                    }
+
+                    println!("Device found! {} ({})", port_name, id);
+                    let active = active.clone();
+                    let state_tx = state_tx.clone();
+                    let transport = config.transport.clone();
+                    let capture = capture.clone();
+                    thread::spawn(move || {
+                        smol::block_on(run_session(
+                            &id, &transport, port_name, usb_info, capture, &state_tx,
+                        ));
+                        // Only now is the port closed, reopening it earlier would fail with EBUSY
+                        active.lock().unwrap().remove(&id);
+                    });
+                }
+            }
+            Err(e) => {
+                println!("Failed to list ports! {}", e);
+            }
+        }
+
+        if active.lock().unwrap().is_empty() {
+            let _ = state_tx.unbounded_send(ChannelSend::ConnectionStateUpdate(
+                None,
+                ConnectionState::Scanning,
+            ));
+        }
+        Timer::after(Duration::from_secs(1)).await;
+    }
+}
+
+/// Drives a single device from opening its port until it disconnects. Returns
+/// once the port is closed again.
+async fn run_session(
+    id: &DeviceId,
+    transport: &TransportConfig,
+    port_name: String,
+    usb_info: Option<UsbPortInfo>,
+    capture: Option<Arc<Capture>>,
+    state_tx: &UnboundedSender<ChannelSend>,
+) {
+    let set_state = |state: ConnectionState| {
+        let _ =
+            state_tx.unbounded_send(ChannelSend::ConnectionStateUpdate(Some(id.clone()), state));
+    };
+
+    set_state(ConnectionState::PortFound(port_name.clone()));
+    set_state(ConnectionState::Opening(port_name.clone()));
+    let port = match open_transport(transport, &port_name).await {
+        Ok(port) => port,
+        Err(e) => {
+            // Permissions won't fix themselves, no need to hammer the port
+            let delay = match e {
+                OpenError::PermissionDenied => Duration::from_secs(5),
+                _ => Duration::from_secs(1),
+            };
+            set_state(e.into_state(port_name));
+            Timer::after(delay).await;
+            return;
+        }
+    };
+
+    let port: Box<dyn Transport> = match capture {
+        Some(capture) => Box::new(Recording::new(port, capture, id.clone())),
+        None => port,
+    };
+
+    // The reader blocks on its own handle, so writes never wait for a read
+    let reader = match port.try_clone_transport() {
+        Ok(reader) => reader,
+        Err(e) => {
+            set_state(ConnectionState::Error(format!(
+                "Failed to clone {}: {}",
+                port_name, e
+            )));
+            return;
+        }
+    };
+
+    let (serial_out_tx, serial_out_rx) = futures_channel::mpsc::unbounded::<CommandsOut>();
+    let (sync_tx, mut sync_rx) = futures_channel::mpsc::unbounded::<CommandsIn>();
+    let (lost_tx, mut lost_rx) = futures_channel::mpsc::unbounded::<()>();
+    let link = Arc::new(Mutex::new(Link::new()));
+    let closed = Arc::new(AtomicBool::new(false));
+
+    let writer = spawn_writer(
+        id.clone(),
+        port,
+        link.clone(),
+        serial_out_rx,
+        lost_tx.clone(),
+        state_tx.clone(),
+    );
+    let reader = spawn_reader(
+        id.clone(),
+        reader,
+        link,
+        closed.clone(),
+        sync_tx,
+        lost_tx,
+        state_tx.clone(),
+    );
+
+    let _ = state_tx.unbounded_send(ChannelSend::DeviceInfoUpdate(
+        id.clone(),
+        Some(DeviceInfo {
+            port_name,
+            usb_info,
+            hello: None,
+            serial_out_tx: serial_out_tx.clone(),
+        }),
+    ));
+
+    // Ask the device for its layout and current volume states
+    set_state(ConnectionState::Handshaking);
+    match sync_device(id, &serial_out_tx, &mut sync_rx, state_tx).await {
+        Ok(capabilities) => {
+            set_state(ConnectionState::Connected);
+            // A replay only answers the pings that were captured, supervising it
+            // would declare it lost and start it over once the capture runs out
+            let heartbeat = capabilities.contains(Capabilities::HEARTBEAT)
+                && !matches!(transport, TransportConfig::Replay { .. });
+            // Stay connected until the reader or writer reports the device as
+            // gone, or it stops answering pings
+            let state = smol::future::or(
+                async {
+                    lost_rx.next().await;
+                    println!("Device {} lost, resuming scan...", id);
+                    ConnectionState::Lost
+                },
+                async {
+                    keep_alive(id, heartbeat, &serial_out_tx, &mut sync_rx, state_tx).await;
+                    eprintln!("Device {} stopped answering, reconnecting...", id);
+                    ConnectionState::Error("The device stopped answering".to_string())
+                },
+            )
+            .await;
+            set_state(state);
+        }
+        Err(e) => match e.downcast_ref::<IncompatibleProtocol>() {
+            Some(incompatible) => {
+                eprintln!("Device {} is incompatible: {}", id, incompatible);
+                // Nothing may be sent to it, and reconnecting would only end the
+                // same way, so hold on to the port until it goes away
+                let _ = state_tx.unbounded_send(ChannelSend::DeviceInfoUpdate(id.clone(), None));
+                set_state(ConnectionState::Error(format!(
+                    "Incompatible firmware, {}",
+                    incompatible
+                )));
+                lost_rx.next().await;
+                println!("Incompatible device {} lost, resuming scan...", id);
+            }
+            None => {
+                eprintln!("Failed to sync with device {}: {}", id, e);
+                set_state(ConnectionState::Error(format!("Handshake failed: {}", e)));
+            }
+        },
+    }
+
+    // The flag stops the reader, dropping the senders stops the writer. The
+    // last sender goes once the UI forgets the device info.
+    closed.store(true, Ordering::Relaxed);
+    let _ = state_tx.unbounded_send(ChannelSend::DeviceInfoUpdate(id.clone(), None));
+    drop(serial_out_tx);
+    // Both threads own a handle to the port, which stays open until they end
+    for handle in [writer, reader] {
+        let _ = handle.join();
+    }
+}
+
+enum KeepAliveEvent {
+    Reply(CommandsIn),
+    Closed,
+    PingDue,
+}
+
+/// Pings the device every [`HEARTBEAT_INTERVAL`] and returns once it stopped
+/// answering. Never returns without `heartbeat`, or once the reader is gone,
+/// the session learns about that through its own channel.
+async fn keep_alive(
+    id: &DeviceId,
+    heartbeat: bool,
+    serial_out_tx: &UnboundedSender<CommandsOut>,
+    replies: &mut UnboundedReceiver<CommandsIn>,
+    state_tx: &UnboundedSender<ChannelSend>,
+) {
+    if !heartbeat {
+        return smol::future::pending().await;
+    }
+
+    let mut heartbeat = Heartbeat::new();
+    let mut next_ping = Instant::now();
+    loop {
+        let event = smol::future::or(
+            async {
+                match replies.next().await {
+                    Some(reply) => KeepAliveEvent::Reply(reply),
+                    None => KeepAliveEvent::Closed,
+                }
+            },
+            async {
+                Timer::at(next_ping).await;
+                KeepAliveEvent::PingDue
+            },
+        )
+        .await;
+
+        let stats = heartbeat.stats;
+        match event {
+            KeepAliveEvent::Reply(CommandsIn::Pong(nonce)) => {
+                if !heartbeat.pong(nonce, Instant::now()) {
+                    println!("Ignoring late pong {} from {}", nonce, id);
+                }
+            }
+            // Late answers to a handshake request that was resent
+            KeepAliveEvent::Reply(_) => {}
+            KeepAliveEvent::Closed => return smol::future::pending().await,
+            KeepAliveEvent::PingDue => {
+                let Some(ping) = heartbeat.ping(Instant::now()) else {
+                    return;
+                };
+                let _ = serial_out_tx.unbounded_send(ping);
+                next_ping += HEARTBEAT_INTERVAL;
+            }
+        }
+
+        if heartbeat.stats != stats {
+            let _ =
+                state_tx.unbounded_send(ChannelSend::LatencyUpdate(id.clone(), heartbeat.stats));
+        }
+    }
+}
+
+fn spawn_writer(
+    id: DeviceId,
+    port: Box<dyn Transport>,
+    link: Arc<Mutex<Link>>,
+    serial_out_rx: UnboundedReceiver<CommandsOut>,
+    lost_tx: UnboundedSender<()>,
+    state_tx: UnboundedSender<ChannelSend>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let on_stats = |stats: LinkStats| {
+            let _ = state_tx.unbounded_send(ChannelSend::LinkStatsUpdate(id.clone(), stats));
+        };
+        if let Err(e) = smol::block_on(write_loop(port, &link, serial_out_rx, on_stats)) {
+            eprintln!("Device {} disconnected: {}", id, e);
+            let _ = lost_tx.unbounded_send(());
+        }
+    })
+}
+
+fn spawn_reader(
+    id: DeviceId,
+    port: Box<dyn Transport>,
+    link: Arc<Mutex<Link>>,
+    closed: Arc<AtomicBool>,
+    sync_tx: UnboundedSender<CommandsIn>,
+    lost_tx: UnboundedSender<()>,
+    state_tx: UnboundedSender<ChannelSend>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let on_command = |command: Result<CommandsIn, ProtocolError>| match command {
+            Ok(command) => {
+                println!("Received command: {:?}", command);
+                match command {
+                    CommandsIn::Hello(_) | CommandsIn::SendInfo(_) | CommandsIn::Pong(_) => {
+                        // Handshake replies and pongs are awaited by the session
+                        let _ = sync_tx.unbounded_send(command);
+                    }
+                    CommandsIn::SendVolume(volume_info) => {
+                        let _ = state_tx.unbounded_send(ChannelSend::SliderVolumeUpdate(
+                            id.clone(),
+                            volume_info.channel.into(),
+                            volume_info.position(),
+                        ));
+                    }
+                    CommandsIn::SendVolumes(volumes) => {
+                        let _ = state_tx.unbounded_send(ChannelSend::SlidersVolumesUpdate(
+                            id.clone(),
+                            volumes.into_iter().map(percent_position).collect(),
+                        ));
+                    }
+                    CommandsIn::SendPositions(positions) => {
+                        let _ = state_tx.unbounded_send(ChannelSend::SlidersVolumesUpdate(
+                            id.clone(),
+                            positions,
+                        ));
+                    }
+                    CommandsIn::UpdateProgress(_) | CommandsIn::UpdateFinished(_) => {
+                        let _ =
+                            state_tx.unbounded_send(ChannelSend::UpdateReply(id.clone(), command));
+                    }
+                    CommandsIn::Button(event) => {
+                        let _ =
+                            state_tx.unbounded_send(ChannelSend::ButtonInput(id.clone(), event));
+                    }
+                    CommandsIn::Encoder(event) => {
+                        let _ =
+                            state_tx.unbounded_send(ChannelSend::EncoderInput(id.clone(), event));
+                    }
+                }
+            }
+            Err(e) => eprintln!("Dropping frame: {}", e),
+        };
+        let on_stats = |stats: LinkStats| {
+            let _ = state_tx.unbounded_send(ChannelSend::LinkStatsUpdate(id.clone(), stats));
+        };
+
+        if let Err(e) = read_loop(port, &link, &closed, on_command, on_stats) {
+            eprintln!("Device {} disconnected: {}", id, e);
+            // Hand control back to the session, it tears everything down
+            let _ = lost_tx.unbounded_send(());
+        }
+    })
+}
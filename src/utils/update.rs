@@ -0,0 +1,108 @@
+use std::{path::PathBuf, sync::Arc, thread, time::Instant};
+
+use futures_channel::mpsc::UnboundedSender;
+
+use crate::{
+    ChannelSend, Device,
+    utils::{Capabilities, CommandsIn, ConnectionState, DeviceId, FirmwareImage, Uploader},
+};
+
+/// Reads and parses a firmware image on its own thread, images can be large
+/// or sit on slow drives. The result comes back as
+/// [`ChannelSend::ImageLoaded`].
+pub fn load_image(id: DeviceId, path: PathBuf, state_tx: UnboundedSender<ChannelSend>) {
+    thread::spawn(move || {
+        let image = std::fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|data| FirmwareImage::parse(data).map_err(|e| e.to_string()))
+            .map_err(|e| format!("{}: {}", path.display(), e));
+        let _ = state_tx.unbounded_send(ChannelSend::ImageLoaded(id, image));
+    });
+}
+
+/// Starts the update to a loaded image, or keeps why it can't for the
+/// update page.
+pub fn image_loaded(device: &mut Device, image: Result<FirmwareImage, String>) {
+    match image.and_then(|image| check_image(device, &image).map(|()| image)) {
+        Ok(image) => {
+            device.image_error = None;
+            start_update(device, image);
+        }
+        Err(e) => device.image_error = Some(e),
+    }
+}
+
+/// Checks `device` can take `image`: the device has to support updates, not
+/// be in the middle of one and run an older version than the image.
+fn check_image(device: &Device, image: &FirmwareImage) -> Result<(), String> {
+    if device
+        .update
+        .as_ref()
+        .is_some_and(|uploader| uploader.state().is_active())
+    {
+        return Err("An update is already running".to_string());
+    }
+    let hello = device
+        .info
+        .as_ref()
+        .and_then(|device_info| device_info.hello.as_ref())
+        .ok_or("The device did not report its firmware version")?;
+    if !hello.capabilities.contains(Capabilities::UPDATE) {
+        return Err("This firmware cannot be updated over the link".to_string());
+    }
+
+    if !image.is_upgrade_from(hello.firmware_version) {
+        return Err(format!(
+            "The device already runs {}, the image is {}",
+            hello.firmware_version, image.version
+        ));
+    }
+    Ok(())
+}
+
+pub fn start_update(device: &mut Device, image: FirmwareImage) {
+    println!(
+        "Updating to firmware {} ({} bytes)",
+        image.version,
+        image.size()
+    );
+    let (uploader, start) = Uploader::new(Arc::new(image));
+    device.update = Some(uploader);
+    device.send_command(start);
+}
+
+pub fn handle_update_reply(device: &mut Device, reply: &CommandsIn) {
+    let Some(next) = device
+        .update
+        .as_mut()
+        .and_then(|uploader| uploader.handle(reply))
+    else {
+        return;
+    };
+    device.send_command(next);
+}
+
+/// Whether an update on `device` is waiting on a reply that is overdue. Only
+/// connected devices count, a transfer resumes once the device is back.
+pub fn update_stalled(device: &Device, now: Instant) -> bool {
+    device.connection_state == ConnectionState::Connected
+        && device
+            .update
+            .as_ref()
+            .is_some_and(|uploader| uploader.is_stalled(now))
+}
+
+/// Asks a stalled device how much of the image it holds, so the transfer
+/// carries on from there.
+pub fn resume_stalled_update(device: &mut Device, now: Instant) {
+    if !update_stalled(device, now) {
+        return;
+    }
+    if let Some(next) = device
+        .update
+        .as_mut()
+        .and_then(|uploader| uploader.poll_stall(now))
+    {
+        device.send_command(next);
+    }
+}
@@ -0,0 +1,219 @@
+//! Property tests for the receive path: COBS framing, link layer and codec.
+//! The fuzz target in `fuzz/` covers the same ground with coverage guidance.
+
+use audiomixer_app2::protocol::{
+    ButtonEvent, ButtonEventKind, Capabilities, Codec, CommandsIn, CommandsOut, DeviceButtonData,
+    DeviceInfo, DeviceSliderData, EncoderEvent, FRAME_DELIMITER, FirmwareVersion, HelloInfo,
+    ICON_SIZE, Icon, Inbound, Link, ProtocolError, RenameSliderProps, Rgb, SetDisplayProps,
+    SetLedProps, SetMuteProps, SetSliderActionProps, SetVolumeProps, StartUpdateProps,
+    UpdateChunkProps, VolumeInfo, decode_frame, encode_frame, link_frame,
+};
+use proptest::{collection::vec, prelude::*, sample::Index};
+
+fn feed(inbound: &mut Inbound, bytes: &[u8]) -> Vec<Result<CommandsIn, ProtocolError>> {
+    let mut commands = Vec::new();
+    inbound.feed(&mut Link::new(), bytes, |command| commands.push(command));
+    commands
+}
+
+fn device_command() -> impl Strategy<Value = CommandsIn> {
+    prop_oneof![
+        (
+            any::<u8>(),
+            any::<u8>(),
+            any::<u8>(),
+            any::<u8>(),
+            any::<u16>()
+        )
+            .prop_map(|(protocol_version, major, minor, patch, capabilities)| {
+                CommandsIn::Hello(HelloInfo {
+                    protocol_version,
+                    firmware_version: FirmwareVersion {
+                        major,
+                        minor,
+                        patch,
+                    },
+                    capabilities: Capabilities(capabilities),
+                })
+            }),
+        (
+            vec(("[a-zA-Z0-9 ]{0,16}", "[a-z:]{0,16}"), 0..8),
+            vec(("[a-zA-Z0-9 ]{0,16}", "[a-z:]{0,16}"), 0..4)
+        )
+            .prop_map(|(sliders, buttons)| {
+                CommandsIn::SendInfo(DeviceInfo {
+                    sliders: sliders
+                        .into_iter()
+                        .map(|(name, set_volume_action)| DeviceSliderData {
+                            name,
+                            set_volume_action,
+                        })
+                        .collect(),
+                    buttons: buttons
+                        .into_iter()
+                        .map(|(name, action)| DeviceButtonData {
+                            name,
+                            action,
+                            long_press_action: String::new(),
+                        })
+                        .collect(),
+                    ..Default::default()
+                })
+            }),
+        (1..=u8::MAX, 0..=100u8, proptest::option::of(any::<u16>())).prop_map(
+            |(channel, volume, fine_position)| CommandsIn::SendVolume(VolumeInfo {
+                channel,
+                volume,
+                fine_position,
+            })
+        ),
+        (
+            1..=u8::MAX,
+            prop_oneof![
+                Just(ButtonEventKind::Press),
+                Just(ButtonEventKind::Release),
+                Just(ButtonEventKind::LongPress)
+            ]
+        )
+            .prop_map(|(button, kind)| CommandsIn::Button(ButtonEvent { button, kind })),
+        (1..=u8::MAX, any::<i8>())
+            .prop_map(|(encoder, steps)| CommandsIn::Encoder(EncoderEvent { encoder, steps })),
+        any::<u32>().prop_map(CommandsIn::UpdateProgress),
+        any::<bool>().prop_map(CommandsIn::UpdateFinished),
+        any::<u16>().prop_map(CommandsIn::Pong),
+        vec(0..=100u8, 0..32).prop_map(CommandsIn::SendVolumes),
+        vec(any::<u16>(), 0..32).prop_map(CommandsIn::SendPositions),
+    ]
+}
+
+fn app_command() -> impl Strategy<Value = CommandsOut> {
+    prop_oneof![
+        Just(CommandsOut::Hello),
+        Just(CommandsOut::RequestInfo),
+        (1..=u8::MAX, 0..=100u8, proptest::option::of(any::<u16>())).prop_map(
+            |(channel, volume, fine_position)| CommandsOut::SetVolume(SetVolumeProps {
+                channel,
+                volume,
+                fine_position,
+            })
+        ),
+        (1..=u8::MAX).prop_map(CommandsOut::RequestVolume),
+        (1..=u8::MAX, any::<bool>())
+            .prop_map(|(channel, muted)| CommandsOut::SetMute(SetMuteProps { channel, muted })),
+        (1..=u8::MAX, any::<(u8, u8, u8)>(), any::<u8>()).prop_map(
+            |(channel, (r, g, b), brightness)| CommandsOut::SetLed(SetLedProps {
+                channel,
+                color: Rgb::new(r, g, b),
+                brightness,
+            })
+        ),
+        (
+            1..=u8::MAX,
+            "[a-zA-Z0-9 ]{0,16}",
+            0..=100u8,
+            proptest::option::of(any::<[u16; ICON_SIZE]>())
+        )
+            .prop_map(|(channel, label, percentage, icon)| {
+                CommandsOut::SetDisplay(SetDisplayProps {
+                    channel,
+                    label,
+                    percentage,
+                    icon: icon.map(Icon),
+                })
+            }),
+        (1..=u8::MAX, ".{0,32}").prop_map(|(channel, name)| CommandsOut::RenameSlider(
+            RenameSliderProps { channel, name }
+        )),
+        (1..=u8::MAX, "[a-z:]{0,32}").prop_map(|(channel, action)| CommandsOut::SetSliderAction(
+            SetSliderActionProps { channel, action }
+        )),
+        Just(CommandsOut::SaveConfig),
+        (any::<u32>(), any::<u32>()).prop_map(|(size, checksum)| CommandsOut::StartUpdate(
+            StartUpdateProps { size, checksum }
+        )),
+        (any::<u32>(), any::<u16>(), vec(any::<u8>(), 0..1024)).prop_map(|(offset, crc, data)| {
+            CommandsOut::UpdateChunk(UpdateChunkProps { offset, crc, data })
+        }),
+        Just(CommandsOut::FinishUpdate),
+        any::<u16>().prop_map(CommandsOut::Ping),
+        Just(CommandsOut::RequestVolumes),
+        vec(0..=100u8, 0..32).prop_map(CommandsOut::SetVolumes),
+        vec(any::<u16>(), 0..32).prop_map(CommandsOut::SetPositions),
+    ]
+}
+
+/// What a device sends for `commands`, frame after frame.
+fn device_stream(commands: &[CommandsIn]) -> Vec<u8> {
+    let codec = Codec::new();
+    commands
+        .iter()
+        .enumerate()
+        .flat_map(|(seq, command)| link_frame(seq as u8, &codec.encode_in(command.clone())))
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_bytes_never_panic(bytes in vec(any::<u8>(), 0..4096)) {
+        feed(&mut Inbound::new(), &bytes);
+
+        let codec = Codec::new();
+        let _ = codec.decode(&bytes);
+        let _ = codec.decode_out(&bytes);
+    }
+
+    #[test]
+    fn cobs_round_trips(data in vec(any::<u8>(), 0..1024)) {
+        let frame = encode_frame(&data);
+        let (delimiter, body) = frame.split_last().unwrap();
+
+        prop_assert_eq!(*delimiter, FRAME_DELIMITER);
+        prop_assert!(!body.contains(&FRAME_DELIMITER));
+        prop_assert_eq!(decode_frame(body).unwrap(), data);
+    }
+
+    #[test]
+    fn device_commands_round_trip(commands in vec(device_command(), 1..8)) {
+        let decoded = feed(&mut Inbound::new(), &device_stream(&commands))
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        prop_assert_eq!(decoded, commands);
+    }
+
+    #[test]
+    fn split_reads_decode_the_same(commands in vec(device_command(), 1..8), split in any::<Index>()) {
+        let stream = device_stream(&commands);
+        let (first, second) = stream.split_at(split.index(stream.len()));
+
+        let mut inbound = Inbound::new();
+        let mut decoded = feed(&mut inbound, first);
+        decoded.extend(feed(&mut inbound, second));
+        let decoded = decoded.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+
+        prop_assert_eq!(decoded, commands);
+    }
+
+    #[test]
+    fn garbage_before_a_frame_is_skipped(
+        garbage in vec(any::<u8>(), 0..256),
+        command in device_command(),
+    ) {
+        // The delimiter resynchronises the decoder whatever came before it
+        let mut stream = garbage;
+        stream.push(FRAME_DELIMITER);
+        stream.extend(device_stream(std::slice::from_ref(&command)));
+
+        let decoded = feed(&mut Inbound::new(), &stream);
+        prop_assert_eq!(decoded.last().unwrap().as_ref().ok(), Some(&command));
+    }
+
+    #[test]
+    fn app_commands_round_trip(command in app_command()) {
+        let codec = Codec::new();
+        let payload = codec.encode(command.clone());
+
+        prop_assert_eq!(codec.decode_out(&payload).unwrap(), command);
+    }
+}